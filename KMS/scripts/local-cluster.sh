@@ -0,0 +1,48 @@
+#!/usr/bin/env bash
+# Run a three node threshold KMS on localhost (ports 3001-3003), any two nodes rebuild
+# a client key. Every pair of nodes gets its own peer token.
+#
+#   KMS_VERIFYING_CONTRACT=0x... ./scripts/local-cluster.sh
+#   curl -X POST -H "Authorization: Bearer $ADMIN_API_TOKEN" http://127.0.0.1:3001/keys/generate
+#
+# State and logs go to $CLUSTER_DIR (./cluster by default), Ctrl-C stops all nodes.
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+: "${KMS_VERIFYING_CONTRACT:?set KMS_VERIFYING_CONTRACT to the KMSVerifier address}"
+CLUSTER_DIR=${CLUSTER_DIR:-./cluster}
+export ADMIN_API_TOKEN=${ADMIN_API_TOKEN:-local-admin}
+export KMS_VERIFYING_CONTRACT
+
+token() {
+    local a=$(( $1 < $2 ? $1 : $2 )) b=$(( $1 < $2 ? $2 : $1 ))
+    local file="$CLUSTER_DIR/peer-token-$a-$b"
+    [ -f "$file" ] || head -c 32 /dev/urandom | od -An -tx1 | tr -d ' \n' > "$file"
+    cat "$file"
+}
+
+# Key generation is far too slow unoptimized
+cargo build --release --quiet
+mkdir -p "$CLUSTER_DIR"
+trap 'kill 0' EXIT
+
+for node in 1 2 3; do
+    peers=() tokens=()
+    for peer in 1 2 3; do
+        [ "$peer" = "$node" ] && continue
+        peers+=("$peer=http://127.0.0.1:300$peer")
+        tokens+=("$peer:$(token "$node" "$peer")")
+    done
+    mkdir -p "$CLUSTER_DIR/node$node"
+    [ -f "$CLUSTER_DIR/node$node/passphrase" ] || head -c 32 /dev/urandom > "$CLUSTER_DIR/node$node/passphrase"
+    PORT=300$node \
+    KEYS_DIR="$CLUSTER_DIR/node$node/keys" \
+    AUDIT_LOG="$CLUSTER_DIR/node$node/audit.log" \
+    KMS_KEK_FILE="$CLUSTER_DIR/node$node/passphrase" \
+    KMS_NODE_INDEX=$node \
+    KMS_PEERS=$(IFS=,; echo "${peers[*]}") \
+    KMS_PEER_TOKENS=$(IFS=,; echo "${tokens[*]}") \
+        ./target/release/KMS > "$CLUSTER_DIR/node$node/kms.log" 2>&1 &
+    echo "node $node on http://127.0.0.1:300$node, logging to $CLUSTER_DIR/node$node/kms.log"
+done
+wait
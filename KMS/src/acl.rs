@@ -0,0 +1,98 @@
+use alloy::primitives::{Address, B256};
+use alloy::providers::{DynProvider, Provider, ProviderBuilder};
+use alloy::sol;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+sol! {
+    #[sol(rpc)]
+    contract ACL {
+        function isAllowed(bytes32 handle, address account) external view returns (bool);
+        function isAllowedForDecryption(bytes32 handle) external view returns (bool);
+    }
+}
+
+// A single permission the ACL is asked about
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AclQuery {
+    // `account` may use `handle` (user decryption needs both the user and the contract)
+    Allowed(B256, Address),
+    // `handle` was marked for public decryption
+    AllowedForDecryption(B256),
+}
+
+impl AclQuery {
+    pub fn handle(&self) -> B256 {
+        match self {
+            AclQuery::Allowed(handle, _) | AclQuery::AllowedForDecryption(handle) => *handle,
+        }
+    }
+}
+
+// Answers are only valid for the block they were read at
+#[derive(Default)]
+struct AclCache {
+    block: u64,
+    answers: HashMap<AclQuery, bool>,
+}
+
+// AclClient answers permission questions against the on-chain ACL
+// Every check reads the chain at the current head and the cache is dropped as soon
+// as the head moves, so a revoked permission is never served from cache
+#[derive(Clone)]
+pub struct AclClient {
+    acl: ACL::ACLInstance<DynProvider>,
+    cache: Arc<Mutex<AclCache>>,
+}
+
+impl AclClient {
+    pub fn new(rpc_url: &str, address: Address) -> Result<Self> {
+        let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?).erased();
+        println!("[AclClient] ACL {} via {}", address, rpc_url);
+        Ok(Self {
+            acl: ACL::new(address, provider),
+            cache: Arc::new(Mutex::new(AclCache::default())),
+        })
+    }
+
+    // Returns the first query the ACL denies, None when everything is allowed
+    pub async fn first_denied(&self, queries: &[AclQuery]) -> Result<Option<AclQuery>> {
+        let block = self.acl.provider().get_block_number().await?;
+        for query in queries {
+            if !self.check(*query, block).await? {
+                return Ok(Some(*query));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn check(&self, query: AclQuery, block: u64) -> Result<bool> {
+        {
+            let mut cache = self.cache.lock().await;
+            if cache.block != block {
+                cache.block = block;
+                cache.answers.clear();
+            }
+            if let Some(allowed) = cache.answers.get(&query) {
+                return Ok(*allowed);
+            }
+        }
+
+        let allowed = match query {
+            AclQuery::Allowed(handle, account) => {
+                self.acl.isAllowed(handle, account).block(block.into()).call().await?
+            }
+            AclQuery::AllowedForDecryption(handle) => {
+                self.acl.isAllowedForDecryption(handle).block(block.into()).call().await?
+            }
+        };
+
+        let mut cache = self.cache.lock().await;
+        if cache.block == block {
+            cache.answers.insert(query, allowed);
+        }
+        Ok(allowed)
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+// AuditLog appends one JSON line per security relevant call (every decryption attempt)
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+#[derive(Serialize)]
+pub struct AuditEvent<'a> {
+    pub action: &'a str,
+    pub caller: String,
+    pub handles: Vec<String>,
+    pub outcome: &'a str,
+}
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    timestamp: u64,
+    #[serde(flatten)]
+    event: AuditEvent<'a>,
+}
+
+impl AuditLog {
+    pub async fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path).await?;
+        println!("[AuditLog] writing to {:?}", path);
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    pub async fn record(&self, event: AuditEvent<'_>) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let record = AuditRecord { timestamp, event };
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                println!("[AuditLog] failed to encode record: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        println!("[AuditLog] {}", String::from_utf8_lossy(&line).trim_end());
+
+        let mut file = self.file.lock().await;
+        if let Err(e) = file.write_all(&line).await {
+            println!("[AuditLog] failed to write record: {}", e);
+        }
+        let _ = file.flush().await;
+    }
+}
@@ -0,0 +1,198 @@
+use alloy::primitives::{Address, Signature, U256};
+use axum::http::{header::AUTHORIZATION, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use crate::eip712::{recover_signer, UserDecryptRequest};
+use crate::error::error_response;
+
+// AuthError is returned as a JSON error body (see error::error_response)
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("missing or invalid bearer token")]
+    InvalidToken,
+    #[error("the {0} role may not call this route")]
+    Forbidden(&'static str),
+    #[error("signature is malformed")]
+    MalformedSignature,
+    #[error("signature was not produced by the user address")]
+    SignerMismatch,
+    #[error("request expired at {0}")]
+    Expired(u64),
+    #[error("expiry is more than {0}s in the future")]
+    ExpiryTooFar(u64),
+    #[error("nonce {0} was already used")]
+    NonceReused(U256),
+    // A peer token used on behalf of another node than its own
+    #[error("the peer token does not belong to node {0}")]
+    WrongPeer(u8),
+}
+
+impl AuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::InvalidToken => "invalid_token",
+            AuthError::Forbidden(_) => "forbidden",
+            AuthError::MalformedSignature => "malformed_signature",
+            AuthError::SignerMismatch => "signer_mismatch",
+            AuthError::Expired(_) => "expired",
+            AuthError::ExpiryTooFar(_) => "expiry_too_far",
+            AuthError::NonceReused(_) => "nonce_reused",
+            AuthError::WrongPeer(_) => "wrong_peer",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AuthError::MalformedSignature | AuthError::ExpiryTooFar(_) => StatusCode::BAD_REQUEST,
+            AuthError::NonceReused(_) => StatusCode::CONFLICT,
+            AuthError::Forbidden(_) | AuthError::WrongPeer(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        error_response(self.status(), self.code(), &self.to_string())
+    }
+}
+
+// Extract the token from an `Authorization: Bearer <token>` header
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Authorizer verifies EIP-712 signed user requests and remembers consumed nonces
+// A nonce only has to be remembered until its request expires, and expiries are
+// capped at `max_ttl`, so the store stays bounded
+pub struct Authorizer {
+    chain_id: u64,
+    max_ttl: u64,
+    // (user, nonce) -> expiry of the request that consumed it
+    used: Mutex<HashMap<(Address, U256), u64>>,
+}
+
+impl Authorizer {
+    pub fn new(chain_id: u64, max_ttl: u64) -> Self {
+        Self {
+            chain_id,
+            max_ttl,
+            used: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Check signature, expiry and nonce of `request` without consuming the nonce, so a
+    // request refused later on (ACL, missing ciphertext) can be retried as signed
+    pub async fn verify(&self, request: &UserDecryptRequest, signature: &[u8]) -> Result<(), AuthError> {
+        let signature = Signature::try_from(signature).map_err(|_| AuthError::MalformedSignature)?;
+        let expiry = expiry(request);
+        let now = now();
+        if expiry < now {
+            return Err(AuthError::Expired(expiry));
+        }
+        if expiry - now > self.max_ttl {
+            return Err(AuthError::ExpiryTooFar(self.max_ttl));
+        }
+        match recover_signer(request, &signature, self.chain_id) {
+            Ok(signer) if signer == request.userAddress => {}
+            _ => return Err(AuthError::SignerMismatch),
+        }
+        if self.used.lock().await.contains_key(&(request.userAddress, request.nonce)) {
+            return Err(AuthError::NonceReused(request.nonce));
+        }
+        Ok(())
+    }
+
+    // Consume the nonce of a verified request once it is about to be answered
+    // Fails if a concurrent request with the same nonce got there first
+    pub async fn consume(&self, request: &UserDecryptRequest) -> Result<(), AuthError> {
+        let now = now();
+        let mut used = self.used.lock().await;
+        used.retain(|_, expires| *expires >= now);
+        if used.contains_key(&(request.userAddress, request.nonce)) {
+            return Err(AuthError::NonceReused(request.nonce));
+        }
+        used.insert((request.userAddress, request.nonce), expiry(request));
+        Ok(())
+    }
+}
+
+fn expiry(request: &UserDecryptRequest) -> u64 {
+    request.expiry.try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{Bytes, B256};
+    use alloy::signers::{local::PrivateKeySigner, SignerSync};
+    use alloy::sol_types::SolStruct;
+    use crate::eip712::domain;
+
+    fn signed(signer: &PrivateKeySigner, nonce: u64, expiry: u64) -> (UserDecryptRequest, Vec<u8>) {
+        let request = UserDecryptRequest {
+            handles: vec![B256::repeat_byte(1)],
+            contractAddress: Address::repeat_byte(2),
+            userAddress: signer.address(),
+            publicKey: Bytes::from(vec![3u8; 32]),
+            nonce: U256::from(nonce),
+            expiry: U256::from(expiry),
+        };
+        let hash = request.eip712_signing_hash(&domain(31337));
+        let signature = signer.sign_hash_sync(&hash).unwrap();
+        (request, signature.as_bytes().to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_authorize() {
+        let authorizer = Authorizer::new(31337, 600);
+        let signer = PrivateKeySigner::random();
+
+        let (request, signature) = signed(&signer, 1, now() + 60);
+        assert!(authorizer.verify(&request, &signature).await.is_ok());
+        // Verifying alone leaves the nonce usable
+        assert!(authorizer.verify(&request, &signature).await.is_ok());
+        assert!(authorizer.consume(&request).await.is_ok());
+        // Replay of the same nonce
+        assert!(matches!(
+            authorizer.verify(&request, &signature).await,
+            Err(AuthError::NonceReused(_))
+        ));
+        assert!(matches!(authorizer.consume(&request).await, Err(AuthError::NonceReused(_))));
+
+        let (request, signature) = signed(&signer, 2, now() - 1);
+        assert!(matches!(authorizer.verify(&request, &signature).await, Err(AuthError::Expired(_))));
+
+        let (request, signature) = signed(&signer, 3, now() + 3600);
+        assert!(matches!(
+            authorizer.verify(&request, &signature).await,
+            Err(AuthError::ExpiryTooFar(_))
+        ));
+
+        // Signed by someone else than the claimed user
+        let (mut request, signature) = signed(&signer, 4, now() + 60);
+        request.userAddress = Address::repeat_byte(9);
+        assert!(matches!(
+            authorizer.verify(&request, &signature).await,
+            Err(AuthError::SignerMismatch)
+        ));
+    }
+}
@@ -0,0 +1,418 @@
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use crypto_box::aead::OsRng;
+use crypto_box::SecretKey;
+use alloy::primitives::B256;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+use crate::auth::constant_time_eq;
+use crate::config::KmsConfig;
+use crate::handlers::user_decrypt::UserDecryptBody;
+use crate::sealing::Sealer;
+use crate::shamir::{self, Share};
+
+// Cluster links the KMS nodes holding shares of the client key
+// Every node keeps one Shamir share per keyset, `threshold` nodes have to hand
+// theirs over before a client key exists again, and then only in the memory of
+// the node running the decryption. Every pair of nodes shares its own token
+// (KMS_PEER_TOKENS), which identifies the calling node. Shares never travel in the
+// clear: pushed shares are sealed under the pair's token and released ones to an
+// ephemeral key. A share is only released for a decryption the releasing node checked
+// itself (ShareGrant), against the ACL and the user's signature.
+pub struct Cluster {
+    node_index: u8,
+    threshold: u8,
+    peers: Vec<Peer>,
+    client: reqwest::Client,
+}
+
+struct Peer {
+    index: u8,
+    url: String,
+    token: String,
+}
+
+// The decryption a share is requested for
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ShareGrant {
+    // Handles the ACL allows for public decryption
+    Public { handles: Vec<B256> },
+    // A user decryption along with the user's EIP-712 signature, its nonce is used up
+    // on every node that releases a share for it
+    User(UserDecryptBody),
+}
+
+impl ShareGrant {
+    pub fn handles(&self) -> &[B256] {
+        match self {
+            ShareGrant::Public { handles } => handles,
+            ShareGrant::User(body) => &body.handles,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShareRequest {
+    // Share index of the requesting node, has to be the node of the token it calls with
+    pub from: u8,
+    // base64 X25519 public key the share is sealed to
+    pub public_key: String,
+    pub grant: ShareGrant,
+}
+
+// A share pushed by the node that generated the keyset
+#[derive(Serialize, Deserialize)]
+pub struct ShareDelivery {
+    // Share index of the sending node, picks the token the share is sealed under
+    pub from: u8,
+    // base64 of the share sealed with the token of the two nodes
+    pub sealed: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShareResponse {
+    pub index: u8,
+    // base64 sealed box of the share
+    pub sealed: String,
+}
+
+impl Cluster {
+    // None when no peers are configured (standalone KMS)
+    pub fn from_config(config: &KmsConfig) -> Result<Option<Self>> {
+        if config.peers.is_empty() {
+            return Ok(None);
+        }
+        let node_index = config.node_index.context("KMS_PEERS is set but KMS_NODE_INDEX is not")?;
+        let mut indices = vec![node_index];
+        for peer in &config.peers {
+            if peer.index == 0 || indices.contains(&peer.index) {
+                bail!("share index {} in KMS_PEERS is zero or used twice", peer.index);
+            }
+            indices.push(peer.index);
+        }
+        let size = indices.len() as u8;
+        let threshold = config.threshold.unwrap_or(size / 2 + 1);
+        if threshold == 0 || threshold > size {
+            bail!("KMS_THRESHOLD must be between 1 and {}, got {}", size, threshold);
+        }
+        println!("[Cluster] node {} of {}, threshold {}", node_index, size, threshold);
+        Ok(Some(Self {
+            node_index,
+            threshold,
+            peers: config
+                .peers
+                .iter()
+                .map(|peer| Peer {
+                    index: peer.index,
+                    url: peer.url.trim_end_matches('/').to_string(),
+                    token: peer.token.clone(),
+                })
+                .collect(),
+            client: reqwest::Client::new(),
+        }))
+    }
+
+    // Node a peer token belongs to, every token is shared by this node and a single peer
+    pub fn peer_of(&self, token: &str) -> Option<u8> {
+        let mut node = None;
+        for peer in &self.peers {
+            if constant_time_eq(peer.token.as_bytes(), token.as_bytes()) {
+                node = Some(peer.index);
+            }
+        }
+        node
+    }
+
+    // Split `secret` and hand every peer its share, returns the share of this node
+    pub async fn distribute(&self, keyset_id: &str, secret: &[u8]) -> Result<Share> {
+        let size = self.peers.len() as u8 + 1;
+        let mut own = None;
+        for share in shamir::split(secret, size, self.threshold)? {
+            if share.index == self.node_index {
+                own = Some(share);
+                continue;
+            }
+            let peer = self
+                .peers
+                .iter()
+                .find(|peer| peer.index == share.index)
+                .ok_or_else(|| anyhow!("no peer holds share {}", share.index))?;
+            self.push_share(peer, keyset_id, &share).await?;
+        }
+        own.ok_or_else(|| anyhow!("share {} was not generated", self.node_index))
+    }
+
+    // Open a share pushed by node `from`, it has to be this node's share of `keyset_id`
+    pub async fn open_share(&self, from: u8, keyset_id: &str, sealed: &str) -> Result<Share> {
+        let peer = self
+            .peers
+            .iter()
+            .find(|peer| peer.index == from)
+            .ok_or_else(|| anyhow!("node {} is not a peer", from))?;
+        let sealer = share_sealer(peer)?;
+        let (label, sealed) = (share_label(keyset_id), BASE64.decode(sealed)?);
+        let bytes = tokio::task::spawn_blocking(move || sealer.open(&sealed, &label)).await??;
+        let share = Share::from_bytes(&bytes)?;
+        if share.index != self.node_index {
+            bail!("node {} sent share {}, this node holds share {}", from, share.index, self.node_index);
+        }
+        Ok(share)
+    }
+
+    // Push an entry (public keys, keyset metadata, active marker) to every peer
+    pub async fn replicate(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        for peer in &self.peers {
+            self.put(peer, name, bytes.to_vec()).await?;
+        }
+        Ok(())
+    }
+
+    // Gather shares from peers until the threshold is met and rebuild the secret
+    // Unreachable peers, and peers refusing `grant`, are skipped as long as enough others answer
+    pub async fn collect(&self, keyset_id: &str, own: Share, grant: &ShareGrant) -> Result<Zeroizing<Vec<u8>>> {
+        let mut shares = vec![own];
+        for peer in &self.peers {
+            if shares.len() >= self.threshold as usize {
+                break;
+            }
+            match self.fetch_share(peer, keyset_id, grant).await {
+                Ok(share) => shares.push(share),
+                Err(e) => println!("[Cluster] no share from node {}: {:#}", peer.index, e),
+            }
+        }
+        if shares.len() < self.threshold as usize {
+            bail!(
+                "only {} of {} key shares available for keyset {}",
+                shares.len(),
+                self.threshold,
+                keyset_id
+            );
+        }
+        shamir::combine(&shares)
+    }
+
+    async fn put(&self, peer: &Peer, name: &str, bytes: Vec<u8>) -> Result<()> {
+        self.client
+            .put(format!("{}/peer/entries/{}", peer.url, name))
+            .bearer_auth(&peer.token)
+            .body(bytes)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("node {} rejected {}", peer.index, name))?;
+        Ok(())
+    }
+
+    async fn push_share(&self, peer: &Peer, keyset_id: &str, share: &Share) -> Result<()> {
+        let sealer = share_sealer(peer)?;
+        let (label, bytes) = (share_label(keyset_id), share.to_bytes());
+        let sealed = tokio::task::spawn_blocking(move || sealer.seal(&bytes, &label)).await??;
+        let delivery = ShareDelivery {
+            from: self.node_index,
+            sealed: BASE64.encode(sealed),
+        };
+        self.client
+            .put(format!("{}/peer/keysets/{}/share", peer.url, keyset_id))
+            .bearer_auth(&peer.token)
+            .json(&delivery)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("node {} rejected its share of keyset {}", peer.index, keyset_id))?;
+        Ok(())
+    }
+
+    // The share travels sealed to a key that only lives for this request
+    async fn fetch_share(&self, peer: &Peer, keyset_id: &str, grant: &ShareGrant) -> Result<Share> {
+        let secret = SecretKey::generate(&mut OsRng);
+        let request = ShareRequest {
+            from: self.node_index,
+            public_key: BASE64.encode(secret.public_key().as_bytes()),
+            grant: grant.clone(),
+        };
+        let response: ShareResponse = self
+            .client
+            .post(format!("{}/peer/keysets/{}/share", peer.url, keyset_id))
+            .bearer_auth(&peer.token)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let sealed = BASE64.decode(&response.sealed)?;
+        let bytes = Zeroizing::new(
+            secret
+                .unseal(&sealed)
+                .map_err(|_| anyhow!("sealed share could not be opened"))?,
+        );
+        let share = Share::from_bytes(&bytes)?;
+        if share.index != peer.index || response.index != peer.index {
+            bail!("node {} answered with share {}", peer.index, share.index);
+        }
+        Ok(share)
+    }
+}
+
+// Only the two nodes of a pair know its token, so only they can open what it seals
+fn share_sealer(peer: &Peer) -> Result<Sealer> {
+    Sealer::new(Zeroizing::new(peer.token.as_bytes().to_vec()))
+}
+
+// Binds a sealed share to its keyset, it can't be replayed as the share of another one
+fn share_label(keyset_id: &str) -> String {
+    format!("keysets/{}/{}", keyset_id, crate::kms::SHARE_ENTRY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use axum::Json;
+    use serde_json::json;
+    use crate::config::PeerConfig;
+    use crate::rbac::Role;
+    use crate::routes::create_router;
+    use crate::state::KmsState;
+
+    // Token of the pair of nodes a and b
+    fn pair_token(a: u8, b: u8) -> String {
+        format!("peer-token-{}-{}", a.min(b), a.max(b))
+    }
+
+    // Serves `app` on a local port, returns its url
+    async fn serve(app: axum::Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, app).into_future());
+        url
+    }
+
+    // A chain whose ACL allows everything, and a coprocessor that computed every
+    // handle under keyset 1a-2b
+    async fn start_chain_and_coprocessor() -> (String, String) {
+        let rpc = axum::Router::new().route(
+            "/",
+            axum::routing::post(|Json(call): Json<serde_json::Value>| async move {
+                let result = match call["method"].as_str() {
+                    Some("eth_blockNumber") => json!("0x1"),
+                    Some("eth_call") => json!(format!("0x{:0>64}", 1)),
+                    _ => serde_json::Value::Null,
+                };
+                Json(json!({ "jsonrpc": "2.0", "id": call["id"], "result": result }))
+            }),
+        );
+        let coprocessor = axum::Router::new().route(
+            "/ciphertexts/{handle}",
+            axum::routing::get(|| async {
+                Json(json!({ "fhe_type": 2, "key_id": "1a-2b", "ciphertext": BASE64.encode(b"ct") }))
+            }),
+        );
+        (serve(rpc).await, serve(coprocessor).await)
+    }
+
+    // Three nodes on localhost, each with the tokens of its two pairs
+    async fn start_cluster(dir: &std::path::Path) -> Vec<KmsConfig> {
+        let (rpc_url, coprocessor_url) = start_chain_and_coprocessor().await;
+        let mut listeners = Vec::new();
+        for _ in 0..3 {
+            listeners.push(tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap());
+        }
+        let urls: Vec<String> = listeners
+            .iter()
+            .map(|l| format!("http://{}", l.local_addr().unwrap()))
+            .collect();
+
+        let mut configs = Vec::new();
+        for (i, listener) in listeners.into_iter().enumerate() {
+            let index = i as u8 + 1;
+            let mut config = KmsConfig::for_tests(&dir.join(index.to_string()));
+            tokio::fs::create_dir_all(dir.join(index.to_string())).await.unwrap();
+            config.node_index = Some(index);
+            config.rpc_url = rpc_url.clone();
+            config.acl_address = Some(alloy::primitives::Address::repeat_byte(0xac));
+            config.coprocessor_url = coprocessor_url.clone();
+            config.peers = (1..=3u8)
+                .filter(|peer| *peer != index)
+                .map(|peer| PeerConfig {
+                    index: peer,
+                    url: urls[peer as usize - 1].clone(),
+                    token: pair_token(index, peer),
+                })
+                .collect();
+            config.api_tokens = config.peers.iter().map(|peer| (peer.token.clone(), Role::Peer)).collect();
+            let app = create_router(KmsState::new(&config).await.unwrap());
+            tokio::spawn(axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).into_future());
+            configs.push(config);
+        }
+        configs
+    }
+
+    #[tokio::test]
+    async fn test_shares_across_nodes() {
+        let dir = std::env::temp_dir().join(format!("kms-cluster-{}", std::process::id()));
+        let configs = start_cluster(&dir).await;
+        let cluster = Cluster::from_config(&configs[0]).unwrap().unwrap();
+        let secret = b"serialized client key".to_vec();
+
+        let mut handle = B256::repeat_byte(1);
+        handle[30] = crate::decryption::FheType::Uint8 as u8;
+        let grant = ShareGrant::Public { handles: vec![handle] };
+
+        let own = cluster.distribute("1a-2b", &secret).await.unwrap();
+        assert_eq!(own.index, 1);
+        assert_eq!(&*cluster.collect("1a-2b", own, &grant).await.unwrap(), &secret);
+
+        // Peers only release a share for a decryption of ciphertexts under its keyset
+        let own = cluster.distribute("5e-6f", &secret).await.unwrap();
+        assert!(cluster.collect("5e-6f", own, &grant).await.is_err());
+
+        // A node's token doesn't collect shares on behalf of another node
+        let response = reqwest::Client::new()
+            .post(format!("{}/peer/keysets/1a-2b/share", configs[0].peers[0].url))
+            .bearer_auth(pair_token(1, 2))
+            .json(&ShareRequest {
+                from: 3,
+                public_key: BASE64.encode([9u8; 32]),
+                grant: grant.clone(),
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 403);
+
+        // A stored share can't be replaced, by a push or through the replicated entries
+        assert!(cluster.distribute("1a-2b", b"another key").await.is_err());
+        let response = reqwest::Client::new()
+            .put(format!("{}/peer/entries/keysets/1a-2b/client_key_share", configs[0].peers[0].url))
+            .bearer_auth(pair_token(1, 2))
+            .body(vec![2u8, 0, 0])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+
+        // Node 3 can't pass a share off as coming from node 1, it doesn't know their token
+        let forged = Cluster::from_config(&configs[2]).unwrap().unwrap();
+        let share = Share::from_bytes(&[2, 7, 7]).unwrap();
+        let sealed = share_sealer(&forged.peers[1])
+            .unwrap()
+            .seal(&share.to_bytes(), &share_label("3c-4d"))
+            .unwrap();
+        let response = reqwest::Client::new()
+            .put(format!("{}/peer/keysets/3c-4d/share", configs[0].peers[0].url))
+            .bearer_auth(pair_token(3, 2))
+            .json(&ShareDelivery {
+                from: 1,
+                sealed: BASE64.encode(sealed),
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+
+        tokio::fs::remove_dir_all(&dir).await.ok();
+    }
+}
@@ -0,0 +1,207 @@
+use alloy::primitives::Address;
+use std::path::PathBuf;
+use crate::rbac::Role;
+
+// KmsConfig collects the environment driven settings of the service
+#[derive(Clone, Debug)]
+pub struct KmsConfig {
+    pub keys_dir: PathBuf,
+    pub port: u16,
+    // Bearer tokens and the role each grants, from KMS_API_TOKENS=`admin:<token>,coprocessor:<token>`,
+    // the single-role ADMIN_API_TOKEN and DECRYPT_API_TOKEN (decrypt) and the peer tokens
+    // A token grants exactly one role
+    pub api_tokens: Vec<(String, Role)>,
+    pub audit_log: PathBuf,
+    // Chain the user decryption signatures are bound to
+    pub chain_id: u64,
+    // Longest validity window accepted for a signed user request
+    pub auth_max_ttl: u64,
+    pub rpc_url: String,
+    // ACL consulted before any decryption, the decrypt routes are closed when unset
+    pub acl_address: Option<Address>,
+    // Coprocessor status API serving final ciphertexts, and the token it requires
+    pub coprocessor_url: String,
+    pub coprocessor_token: Option<String>,
+    // Hex private key of the KMS signer, generated and kept in keys_dir when unset
+    pub signer_key: Option<String>,
+    // EIP-712 domain of the KMSVerifier decryption signatures
+    pub gateway_chain_id: u64,
+    // KMSVerifier address, required
+    pub verifying_contract: Address,
+    // File holding the key-encryption secret, used when KMS_PASSPHRASE is unset
+    pub kek_file: Option<PathBuf>,
+    // Key storage backend (fs, sealed or memory), sealed when unset
+    pub key_store: Option<String>,
+    // Threshold mode: share index of this node (1..=N) and the other nodes, from
+    // KMS_PEERS=`2=http://host:port,3=http://host:port` and KMS_PEER_TOKENS=`2:<token>,3:<token>`
+    pub node_index: Option<u8>,
+    pub peers: Vec<PeerConfig>,
+    // Shares needed to rebuild a client key, a majority of the nodes when unset
+    pub threshold: Option<u8>,
+    // Parameter preset new keysets are generated with unless a request names another one
+    pub tfhe_params: String,
+}
+
+// Another node of the cluster
+#[derive(Clone, Debug)]
+pub struct PeerConfig {
+    pub index: u8,
+    pub url: String,
+    // Bearer token of this pair of nodes, each side presents it to the other and it
+    // also keys the sealing of the client key shares they send each other
+    pub token: String,
+}
+
+impl KmsConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let keys_dir = std::env::var("KEYS_DIR").unwrap_or_else(|_| "./keys".to_string());
+        let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
+        let audit_log = std::env::var("AUDIT_LOG").unwrap_or_else(|_| "./audit.log".to_string());
+        let chain_id = std::env::var("CHAIN_ID").unwrap_or_else(|_| "31337".to_string());
+        let auth_max_ttl = std::env::var("AUTH_MAX_TTL_SECS").unwrap_or_else(|_| "3600".to_string());
+        let acl_address = match std::env::var("ACL_ADDRESS") {
+            Ok(address) => Some(address.parse()?),
+            Err(_) => None,
+        };
+        let chain_id: u64 = chain_id.parse()?;
+        let gateway_chain_id = match std::env::var("GATEWAY_CHAIN_ID") {
+            Ok(id) => id.parse()?,
+            Err(_) => chain_id,
+        };
+        // Signatures for any other contract are useless on chain, so there is no default
+        let verifying_contract = std::env::var("KMS_VERIFYING_CONTRACT")
+            .map_err(|_| anyhow::anyhow!("KMS_VERIFYING_CONTRACT not set, expected the KMSVerifier address"))?
+            .parse()?;
+        let peers = match std::env::var("KMS_PEERS") {
+            Ok(peers) => parse_peers(&peers, &std::env::var("KMS_PEER_TOKENS").unwrap_or_default())?,
+            Err(_) => Vec::new(),
+        };
+        let mut api_tokens = match std::env::var("KMS_API_TOKENS") {
+            Ok(tokens) => parse_api_tokens(&tokens)?,
+            Err(_) => Vec::new(),
+        };
+        for (var, role) in [
+            ("ADMIN_API_TOKEN", Role::Admin),
+            ("DECRYPT_API_TOKEN", Role::Decrypt),
+        ] {
+            if let Ok(token) = std::env::var(var) {
+                api_tokens.push((token, role));
+            }
+        }
+        api_tokens.extend(peers.iter().map(|peer| (peer.token.clone(), Role::Peer)));
+        check_distinct_tokens(&api_tokens)?;
+        Ok(Self {
+            keys_dir: keys_dir.into(),
+            port: port.parse()?,
+            api_tokens,
+            audit_log: audit_log.into(),
+            chain_id,
+            auth_max_ttl: auth_max_ttl.parse()?,
+            rpc_url: std::env::var("RPC_URL").unwrap_or_else(|_| "http://127.0.0.1:8545".to_string()),
+            acl_address,
+            coprocessor_url: std::env::var("COPROCESSOR_URL")
+                .unwrap_or_else(|_| "http://127.0.0.1:4000".to_string()),
+            coprocessor_token: std::env::var("COPROCESSOR_API_TOKEN").ok(),
+            signer_key: std::env::var("KMS_SIGNER_KEY").ok(),
+            gateway_chain_id,
+            verifying_contract,
+            kek_file: std::env::var("KMS_KEK_FILE").ok().map(PathBuf::from),
+            key_store: std::env::var("KEY_STORE").ok(),
+            node_index: std::env::var("KMS_NODE_INDEX").ok().map(|i| i.parse()).transpose()?,
+            peers,
+            threshold: std::env::var("KMS_THRESHOLD").ok().map(|t| t.parse()).transpose()?,
+            tfhe_params: std::env::var("TFHE_PARAMS").unwrap_or_else(|_| "tuniform-2m64".to_string()),
+        })
+    }
+}
+
+fn parse_api_tokens(tokens: &str) -> anyhow::Result<Vec<(String, Role)>> {
+    tokens
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (role, token) = entry
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("KMS_API_TOKENS entry is not <role>:<token>"))?;
+            let role = Role::parse(role).ok_or_else(|| {
+                anyhow::anyhow!("unknown role {:?} in KMS_API_TOKENS, expected admin, coprocessor, decrypt or peer", role)
+            })?;
+            if token.is_empty() {
+                anyhow::bail!("empty {} token in KMS_API_TOKENS", role.name());
+            }
+            Ok((token.to_string(), role))
+        })
+        .collect()
+}
+
+// A token listed for two roles would grant whichever matches last, refuse it instead
+fn check_distinct_tokens(tokens: &[(String, Role)]) -> anyhow::Result<()> {
+    for (i, (token, role)) in tokens.iter().enumerate() {
+        if let Some((_, other)) = tokens[i + 1..].iter().find(|(t, _)| t == token) {
+            anyhow::bail!("the same API token is configured for the {} and {} roles", role.name(), other.name());
+        }
+    }
+    Ok(())
+}
+
+// Every peer needs its own token, shared only by this node and that peer
+fn parse_peers(peers: &str, tokens: &str) -> anyhow::Result<Vec<PeerConfig>> {
+    let tokens = tokens
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (index, token) = entry
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("KMS_PEER_TOKENS entry is not <index>:<token>"))?;
+            Ok((index.parse::<u8>()?, token.to_string()))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    peers
+        .split(',')
+        .filter(|peer| !peer.trim().is_empty())
+        .map(|peer| {
+            let (index, url) = peer
+                .trim()
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("KMS_PEERS entry {:?} is not <index>=<url>", peer))?;
+            let index: u8 = index.parse()?;
+            let token = tokens
+                .iter()
+                .find(|(i, _)| *i == index)
+                .map(|(_, token)| token.clone())
+                .filter(|token| !token.is_empty())
+                .ok_or_else(|| anyhow::anyhow!("no token for node {} in KMS_PEER_TOKENS", index))?;
+            Ok(PeerConfig { index, url: url.to_string(), token })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+impl KmsConfig {
+    // Standalone node keeping its keys in memory, with no tokens and no chain
+    pub fn for_tests(dir: &std::path::Path) -> Self {
+        Self {
+            keys_dir: dir.join("keys"),
+            port: 0,
+            api_tokens: Vec::new(),
+            audit_log: dir.join("audit.log"),
+            chain_id: 31337,
+            auth_max_ttl: 600,
+            rpc_url: "http://127.0.0.1:8545".to_string(),
+            acl_address: None,
+            coprocessor_url: "http://127.0.0.1:4000".to_string(),
+            coprocessor_token: None,
+            signer_key: None,
+            gateway_chain_id: 31337,
+            verifying_contract: Address::repeat_byte(1),
+            kek_file: None,
+            key_store: Some("memory".to_string()),
+            node_index: None,
+            peers: Vec::new(),
+            threshold: None,
+            tfhe_params: "tuniform-2m64".to_string(),
+        }
+    }
+}
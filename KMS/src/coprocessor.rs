@@ -0,0 +1,110 @@
+use alloy::primitives::B256;
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use crate::decryption::FheType;
+
+#[derive(Deserialize)]
+struct CiphertextResponse {
+    fhe_type: FheType,
+    key_id: String,
+    ciphertext: String,
+}
+
+// Final ciphertext of a handle, computed under the server key of keyset `key_id`
+#[derive(Debug, PartialEq)]
+pub struct Ciphertext {
+    pub bytes: Vec<u8>,
+    pub fhe_type: FheType,
+    pub key_id: String,
+}
+
+// CoprocessorClient fetches final ciphertexts from the coprocessor status API
+#[derive(Clone)]
+pub struct CoprocessorClient {
+    url: String,
+    // Bearer token the coprocessor requires on /ciphertexts (COPROCESSOR_API_TOKEN)
+    token: Option<String>,
+    http: Client,
+}
+
+impl CoprocessorClient {
+    pub fn new(url: String, token: Option<String>) -> Self {
+        Self {
+            url,
+            token,
+            http: Client::new(),
+        }
+    }
+
+    // None when the coprocessor has no final ciphertext for the handle
+    pub async fn ciphertext(&self, handle: B256) -> Result<Option<Ciphertext>> {
+        let mut request = self.http.get(format!("{}/ciphertexts/{}", self.url, handle));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(anyhow!("coprocessor returned {}", response.status()));
+        }
+        let body: CiphertextResponse = response.json().await?;
+        // The handle fixes the type, a ciphertext of another one is not the handle's value
+        if FheType::try_from(handle[30]).ok() != Some(body.fhe_type) {
+            bail!(
+                "coprocessor returned a {:?} ciphertext for handle {} of type {}",
+                body.fhe_type,
+                handle,
+                handle[30]
+            );
+        }
+        Ok(Some(Ciphertext {
+            bytes: BASE64.decode(body.ciphertext)?,
+            fhe_type: body.fhe_type,
+            key_id: body.key_id,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::HeaderMap, routing::get, Json, Router};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_ciphertext_type_and_token() {
+        // Answers every handle with an euint8, and only to the right token
+        let app = Router::new().route(
+            "/ciphertexts/{handle}",
+            get(|headers: HeaderMap| async move {
+                if crate::auth::bearer_token(&headers) != Some("secret") {
+                    return Err(axum::http::StatusCode::UNAUTHORIZED);
+                }
+                Ok(Json(json!({ "fhe_type": 2, "key_id": "1-aa", "ciphertext": BASE64.encode(b"ct") })))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let mut uint8 = B256::repeat_byte(1);
+        uint8[30] = FheType::Uint8 as u8;
+        let mut uint64 = uint8;
+        uint64[30] = FheType::Uint64 as u8;
+
+        let client = CoprocessorClient::new(url.clone(), Some("secret".to_string()));
+        let ciphertext = Ciphertext {
+            bytes: b"ct".to_vec(),
+            fhe_type: FheType::Uint8,
+            key_id: "1-aa".to_string(),
+        };
+        assert_eq!(client.ciphertext(uint8).await.unwrap(), Some(ciphertext));
+        assert!(client.ciphertext(uint64).await.is_err());
+        assert!(CoprocessorClient::new(url, None).ciphertext(uint8).await.is_err());
+    }
+}
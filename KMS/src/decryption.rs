@@ -0,0 +1,91 @@
+use anyhow::{anyhow, Result};
+use crypto_box::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use tfhe::integer::U256;
+use tfhe::prelude::*;
+use tfhe::{
+    ClientKey, FheBool, FheUint128, FheUint16, FheUint160, FheUint256, FheUint32, FheUint4,
+    FheUint64, FheUint8,
+};
+
+// FheType mirrors the on-chain FheType enum (same numbering as the coprocessor)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum FheType {
+    Bool = 0,
+    Uint4 = 1,
+    Uint8 = 2,
+    Uint16 = 3,
+    Uint32 = 4,
+    Uint64 = 5,
+    Uint128 = 6,
+    Uint160 = 7,
+    Uint256 = 8,
+}
+
+impl TryFrom<u8> for FheType {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(FheType::Bool),
+            1 => Ok(FheType::Uint4),
+            2 => Ok(FheType::Uint8),
+            3 => Ok(FheType::Uint16),
+            4 => Ok(FheType::Uint32),
+            5 => Ok(FheType::Uint64),
+            6 => Ok(FheType::Uint128),
+            7 => Ok(FheType::Uint160),
+            8 => Ok(FheType::Uint256),
+            other => Err(format!("unsupported fhe type {}", other)),
+        }
+    }
+}
+
+impl From<FheType> for u8 {
+    fn from(value: FheType) -> Self {
+        value as u8
+    }
+}
+
+// Decrypt a serialized ciphertext into its 32 byte big-endian plaintext (the on-chain
+// uint256 representation, addresses are right aligned)
+// The coprocessor stores every handle's ciphertext on its own, inputs included: it
+// expands client lists once their proof checked out
+pub fn decrypt_ciphertext(bytes: &[u8], fhe_type: FheType, key: &ClientKey) -> Result<[u8; 32]> {
+    let plaintext = match fhe_type {
+        FheType::Bool => {
+            let value: bool = bincode::deserialize::<FheBool>(bytes)?.decrypt(key);
+            small(value as u128)
+        }
+        FheType::Uint4 => small(bincode::deserialize::<FheUint4>(bytes)?.decrypt(key)),
+        FheType::Uint8 => small(bincode::deserialize::<FheUint8>(bytes)?.decrypt(key)),
+        FheType::Uint16 => small(bincode::deserialize::<FheUint16>(bytes)?.decrypt(key)),
+        FheType::Uint32 => small(bincode::deserialize::<FheUint32>(bytes)?.decrypt(key)),
+        FheType::Uint64 => small(bincode::deserialize::<FheUint64>(bytes)?.decrypt(key)),
+        FheType::Uint128 => small(bincode::deserialize::<FheUint128>(bytes)?.decrypt(key)),
+        FheType::Uint160 => big(bincode::deserialize::<FheUint160>(bytes)?.decrypt(key)),
+        FheType::Uint256 => big(bincode::deserialize::<FheUint256>(bytes)?.decrypt(key)),
+    };
+    Ok(plaintext)
+}
+
+fn small(value: u128) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out[16..].copy_from_slice(&value.to_be_bytes());
+    out
+}
+
+fn big(value: U256) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    value.copy_to_be_byte_slice(&mut out);
+    out
+}
+
+// Seal a plaintext to a user's X25519 public key (NaCl sealed box), only the
+// holder of the matching secret key can open it
+pub fn seal_to(public_key: [u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+    crypto_box::PublicKey::from(public_key)
+        .seal(&mut OsRng, plaintext)
+        .map_err(|_| anyhow!("failed to seal plaintext"))
+}
@@ -0,0 +1,55 @@
+use alloy::primitives::{Address, Signature};
+use alloy::sol;
+use alloy::sol_types::{eip712_domain, Eip712Domain, SolStruct};
+use anyhow::Result;
+
+sol! {
+    // Signed by the user to have `handles` re-encrypted under `publicKey`
+    // `nonce` can only be used once per user, `expiry` is a unix timestamp in seconds
+    #[derive(Debug)]
+    struct UserDecryptRequest {
+        bytes32[] handles;
+        address contractAddress;
+        address userAddress;
+        bytes publicKey;
+        uint256 nonce;
+        uint256 expiry;
+    }
+
+    // Checked by the KMSVerifier host contract for public decryption results
+    #[derive(Debug)]
+    struct PublicDecryptVerification {
+        bytes32[] ctHandles;
+        bytes decryptedResult;
+        bytes extraData;
+    }
+}
+
+// Domain every KMS typed-data signature is bound to
+pub fn domain(chain_id: u64) -> Eip712Domain {
+    eip712_domain! {
+        name: "KMS",
+        version: "1",
+        chain_id: chain_id,
+    }
+}
+
+// Domain KMSVerifier uses for decryption results
+pub fn decryption_domain(gateway_chain_id: u64, verifying_contract: Address) -> Eip712Domain {
+    eip712_domain! {
+        name: "Decryption",
+        version: "1",
+        chain_id: gateway_chain_id,
+        verifying_contract: verifying_contract,
+    }
+}
+
+// Recover the address that signed `request`
+pub fn recover_signer(
+    request: &UserDecryptRequest,
+    signature: &Signature,
+    chain_id: u64,
+) -> Result<Address> {
+    let hash = request.eip712_signing_hash(&domain(chain_id));
+    Ok(signature.recover_address_from_prehash(&hash)?)
+}
@@ -0,0 +1,265 @@
+use axum::{
+    extract::Request,
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use crypto_box::aead::rand_core::RngCore;
+use crypto_box::aead::OsRng;
+use serde_json::json;
+use thiserror::Error;
+use alloy::primitives::B256;
+use crate::auth::AuthError;
+use crate::jobs::KeygenConflict;
+use crate::kms::KeysetError;
+use crate::params::PresetError;
+
+tokio::task_local! {
+    // Id of the request being handled, set by the request_id middleware
+    static REQUEST_ID: String;
+}
+
+// KmsError is what the key handlers fail with, returned as
+// `{"error": <code>, "message": <text>, "request_id": <id>}`
+// The codes are stable, clients may match on them
+#[derive(Debug, Error)]
+pub enum KmsError {
+    #[error(transparent)]
+    Keyset(#[from] KeysetError),
+    #[error(transparent)]
+    Params(#[from] PresetError),
+    #[error("job {0} not found")]
+    JobNotFound(u64),
+    // A keyset entry a peer may not push
+    #[error("{0} is not a replicated keyset entry")]
+    InvalidEntry(String),
+    #[error("public key must be 32 bytes of X25519")]
+    InvalidPublicKey,
+    // A pushed key share that doesn't open or isn't meant for this node
+    #[error("key share was refused")]
+    InvalidShare,
+    #[error(transparent)]
+    KeygenConflict(#[from] KeygenConflict),
+    // A stored key that no longer deserializes
+    #[error("stored key is corrupt: {0}")]
+    CorruptKey(String),
+    // The key store failed to read or write
+    #[error("key storage failed: {0}")]
+    Storage(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl KmsError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            KmsError::Keyset(KeysetError::NotFound(_)) => "keyset_not_found",
+            KmsError::Keyset(KeysetError::NoActive) => "no_active_keyset",
+            KmsError::Keyset(KeysetError::KeyMissing(_)) => "key_not_found",
+            KmsError::Params(e) => e.code(),
+            KmsError::JobNotFound(_) => "job_not_found",
+            KmsError::InvalidEntry(_) => "invalid_entry",
+            KmsError::InvalidPublicKey => "invalid_public_key",
+            KmsError::InvalidShare => "invalid_share",
+            KmsError::KeygenConflict(_) => "keygen_conflict",
+            KmsError::CorruptKey(_) => "corrupt_key",
+            KmsError::Storage(_) => "storage_error",
+            KmsError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            KmsError::Keyset(_) | KmsError::JobNotFound(_) => StatusCode::NOT_FOUND,
+            KmsError::Params(_) | KmsError::InvalidEntry(_) | KmsError::InvalidPublicKey | KmsError::InvalidShare => {
+                StatusCode::BAD_REQUEST
+            }
+            KmsError::KeygenConflict(_) => StatusCode::CONFLICT,
+            KmsError::CorruptKey(_) | KmsError::Storage(_) | KmsError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    // What the caller gets to read: server side failures carry paths and library
+    // errors, those only go to the log under the request id
+    fn public_message(&self) -> String {
+        match self {
+            KmsError::CorruptKey(_) => "stored key is corrupt".to_string(),
+            KmsError::Storage(_) => "key storage failed".to_string(),
+            KmsError::Internal(_) => "internal error".to_string(),
+            e => e.to_string(),
+        }
+    }
+}
+
+// Classify the errors coming out of KmsService by their cause
+impl From<anyhow::Error> for KmsError {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<KeysetError>() {
+            Ok(e) => return KmsError::Keyset(e),
+            Err(e) => e,
+        };
+        if e.chain().any(|cause| cause.is::<bincode::ErrorKind>() || cause.is::<bincode::Error>()) {
+            return KmsError::CorruptKey(format!("{:#}", e));
+        }
+        if e.chain().any(|cause| cause.is::<std::io::Error>()) {
+            return KmsError::Storage(format!("{:#}", e));
+        }
+        KmsError::Internal(format!("{:#}", e))
+    }
+}
+
+impl IntoResponse for KmsError {
+    fn into_response(self) -> Response {
+        if self.status().is_server_error() {
+            println!("[KMS] request {} failed: {}", request_id(), self);
+        }
+        error_response(self.status(), self.code(), &self.public_message())
+    }
+}
+
+// DecryptError is what the decryption endpoints refuse a request with
+#[derive(Debug, Error)]
+pub enum DecryptError {
+    #[error("no handles to decrypt")]
+    EmptyRequest,
+    // Decryption is refused outright without an ACL to check against
+    #[error("ACL is not configured")]
+    AclNotConfigured,
+    #[error("ACL denies access to {0}")]
+    AclDenied(B256),
+    #[error("ACL could not be queried")]
+    AclUnavailable,
+    #[error("no ciphertext for {0}")]
+    CiphertextNotFound(B256),
+    #[error("coprocessor could not be queried")]
+    CoprocessorUnavailable,
+    // Ciphertexts only decrypt under the keyset the coprocessor computed them with
+    #[error("{handle} is under keyset {key_id}, not {expected}")]
+    KeysetMismatch { handle: B256, key_id: String, expected: String },
+    #[error("decryption failed")]
+    DecryptionFailed,
+    #[error("signing the decryption failed")]
+    SigningFailed,
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    #[error(transparent)]
+    Kms(#[from] KmsError),
+}
+
+impl DecryptError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            DecryptError::EmptyRequest => "empty_request",
+            DecryptError::AclNotConfigured => "acl_not_configured",
+            DecryptError::AclDenied(_) => "acl_denied",
+            DecryptError::AclUnavailable => "acl_unavailable",
+            DecryptError::CiphertextNotFound(_) => "ciphertext_not_found",
+            DecryptError::CoprocessorUnavailable => "coprocessor_unavailable",
+            DecryptError::KeysetMismatch { .. } => "keyset_mismatch",
+            DecryptError::DecryptionFailed => "decryption_failed",
+            DecryptError::SigningFailed => "signing_failed",
+            DecryptError::Auth(e) => e.code(),
+            DecryptError::Kms(e) => e.code(),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            DecryptError::EmptyRequest => StatusCode::BAD_REQUEST,
+            DecryptError::AclNotConfigured => StatusCode::SERVICE_UNAVAILABLE,
+            DecryptError::AclDenied(_) => StatusCode::FORBIDDEN,
+            DecryptError::AclUnavailable | DecryptError::CoprocessorUnavailable => StatusCode::BAD_GATEWAY,
+            DecryptError::CiphertextNotFound(_) => StatusCode::NOT_FOUND,
+            DecryptError::KeysetMismatch { .. } => StatusCode::CONFLICT,
+            DecryptError::DecryptionFailed | DecryptError::SigningFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            DecryptError::Auth(e) => e.status(),
+            DecryptError::Kms(e) => e.status(),
+        }
+    }
+}
+
+impl IntoResponse for DecryptError {
+    fn into_response(self) -> Response {
+        match self {
+            DecryptError::Auth(e) => e.into_response(),
+            DecryptError::Kms(e) => e.into_response(),
+            e => error_response(e.status(), e.code(), &e.to_string()),
+        }
+    }
+}
+
+// JSON error body shared by every error type of the service
+pub fn error_response(status: StatusCode, code: &str, message: &str) -> Response {
+    let body = json!({ "error": code, "message": message, "request_id": request_id() });
+    (status, Json(body)).into_response()
+}
+
+// Id of the current request, "-" outside of one
+pub fn request_id() -> String {
+    REQUEST_ID.try_with(Clone::clone).unwrap_or_else(|_| "-".to_string())
+}
+
+// Tag every request with an id, the caller's X-Request-Id when it sent a usable one
+// The id is echoed in the X-Request-Id response header and in error bodies
+pub async fn assign_request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            let mut id = [0u8; 8];
+            OsRng.fill_bytes(&mut id);
+            hex::encode(id)
+        });
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert("x-request-id", value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[tokio::test]
+    async fn test_error_mapping() {
+        let missing = KmsError::from(anyhow::Error::from(KeysetError::NoActive));
+        assert_eq!((missing.code(), missing.status()), ("no_active_keyset", StatusCode::NOT_FOUND));
+
+        let corrupt: bincode::Error = bincode::deserialize::<u64>(&[1]).unwrap_err();
+        let corrupt = KmsError::from(anyhow::Error::from(corrupt).context("keysets/a/public_key"));
+        assert_eq!(corrupt.code(), "corrupt_key");
+
+        let io = Err::<(), _>(std::io::Error::other("disk gone")).context("writing active").unwrap_err();
+        let io = KmsError::from(io);
+        assert_eq!((io.code(), io.status()), ("storage_error", StatusCode::INTERNAL_SERVER_ERROR));
+
+        let response = REQUEST_ID
+            .scope("req-1".to_string(), async { KmsError::JobNotFound(7).into_response() })
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "job_not_found");
+        assert_eq!(body["request_id"], "req-1");
+
+        // Server side details stay in the log
+        let response = KmsError::Internal("/var/lib/kms/keysets/a: permission denied".into()).into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!((body["error"].as_str(), body["message"].as_str()), (Some("internal_error"), Some("internal error")));
+
+        let response = DecryptError::AclDenied(B256::ZERO).into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "acl_denied");
+    }
+}
@@ -0,0 +1,193 @@
+use std::net::SocketAddr;
+use alloy::primitives::{Bytes, B256};
+use axum::{
+    extract::{ConnectInfo, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use crate::acl::AclQuery;
+use crate::audit::AuditEvent;
+use crate::auth::AuthError;
+use crate::cluster::ShareGrant;
+use crate::decryption::{decrypt_ciphertext, FheType};
+use crate::signer::decryption_proof;
+use crate::error::{DecryptError, KmsError};
+use crate::state::KmsState;
+
+// Only handles are taken, their ciphertexts come from the coprocessor: a caller can't
+// have the KMS decrypt and sign a ciphertext of its choosing under a public handle
+#[derive(Deserialize)]
+pub struct DecryptRequest {
+    pub handles: Vec<B256>,
+    // Keyset the ciphertexts are expected under, refused when the coprocessor computed
+    // them under another one
+    #[serde(default)]
+    pub key_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DecryptResponse {
+    pub plaintexts: Vec<Plaintext>,
+    // EIP-712 PublicDecryptVerification signature of the KMS signer
+    pub signature: Bytes,
+    // Proof accepted by KMSVerifier.verifyDecryptionEIP712KMSSignatures
+    pub decryption_proof: Bytes,
+}
+
+#[derive(Serialize)]
+pub struct Plaintext {
+    pub handle: B256,
+    // 0x prefixed, 32 byte big-endian value
+    pub value: String,
+}
+
+pub async fn decrypt(
+    State(state): State<KmsState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<DecryptRequest>,
+) -> Result<Json<DecryptResponse>, DecryptError> {
+    let handles = &request.handles;
+    let audit = |outcome| AuditEvent {
+        action: "decrypt",
+        caller: addr.to_string(),
+        handles: handles.iter().map(|h| h.to_string()).collect(),
+        outcome,
+    };
+
+    if let Err(e) = authorize_public(&state, handles).await {
+        state.audit.record(audit(outcome(&e))).await;
+        return Err(e);
+    }
+
+    let (key_id, inputs) = match ciphertexts(&state, handles, request.key_id.as_deref()).await {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            state.audit.record(audit(outcome(&e))).await;
+            return Err(e);
+        }
+    };
+
+    let grant = ShareGrant::Public { handles: handles.clone() };
+    let client_key = match state.kms_service.load_client(Some(&key_id), &grant).await {
+        Ok(key) => key,
+        Err(e) => {
+            println!("[decrypt] client key unavailable: {}", e);
+            state.audit.record(audit("client key unavailable")).await;
+            return Err(KmsError::from(e).into());
+        }
+    };
+
+    // Decryption is CPU bound, keep it off the async workers
+    let result = tokio::task::spawn_blocking(move || {
+        inputs
+            .iter()
+            .map(|(_, bytes, fhe_type)| decrypt_ciphertext(bytes, *fhe_type, &client_key.key))
+            .collect::<anyhow::Result<Vec<_>>>()
+    })
+    .await
+    .map_err(|e| KmsError::Internal(e.to_string()))?;
+
+    let values = match result {
+        Ok(values) => values,
+        Err(e) => {
+            println!("[decrypt] failed: {}", e);
+            state.audit.record(audit("decryption failed")).await;
+            return Err(DecryptError::DecryptionFailed);
+        }
+    };
+    let signature = match state.signer.sign_public_decryption(handles, &values) {
+        Ok(signature) => signature,
+        Err(e) => {
+            println!("[decrypt] signing failed: {}", e);
+            state.audit.record(audit("signing failed")).await;
+            return Err(DecryptError::SigningFailed);
+        }
+    };
+    state.audit.record(audit("ok")).await;
+
+    let plaintexts = handles
+        .iter()
+        .zip(values)
+        .map(|(handle, value)| Plaintext {
+            handle: *handle,
+            value: format!("0x{}", hex::encode(value)),
+        })
+        .collect();
+    Ok(Json(DecryptResponse {
+        plaintexts,
+        decryption_proof: decryption_proof(std::slice::from_ref(&signature)),
+        signature,
+    }))
+}
+
+// Final ciphertexts of `handles`, as the coprocessor computed them, along with the keyset
+// that decrypts them. All must be under one keyset, `key_id` when the caller named one.
+pub async fn ciphertexts(
+    state: &KmsState,
+    handles: &[B256],
+    key_id: Option<&str>,
+) -> Result<(String, Vec<(B256, Vec<u8>, FheType)>), DecryptError> {
+    let mut keyset = key_id.map(str::to_string);
+    let mut inputs = Vec::with_capacity(handles.len());
+    for handle in handles {
+        let ciphertext = match state.coprocessor.ciphertext(*handle).await {
+            Ok(Some(ciphertext)) => ciphertext,
+            Ok(None) => return Err(DecryptError::CiphertextNotFound(*handle)),
+            Err(e) => {
+                println!("[decrypt] coprocessor query for {} failed: {:#}", handle, e);
+                return Err(DecryptError::CoprocessorUnavailable);
+            }
+        };
+        let expected = keyset.get_or_insert_with(|| ciphertext.key_id.clone());
+        if *expected != ciphertext.key_id {
+            return Err(DecryptError::KeysetMismatch {
+                handle: *handle,
+                key_id: ciphertext.key_id,
+                expected: expected.clone(),
+            });
+        }
+        inputs.push((*handle, ciphertext.bytes, ciphertext.fhe_type));
+    }
+    // Handles are never empty here, the callers refuse empty requests first
+    Ok((keyset.unwrap_or_default(), inputs))
+}
+
+// Only handles a contract explicitly made public may be returned in the clear
+// Peers run the same check before releasing their key share for the decryption
+pub async fn authorize_public(state: &KmsState, handles: &[B256]) -> Result<(), DecryptError> {
+    if handles.is_empty() {
+        return Err(DecryptError::EmptyRequest);
+    }
+    let Some(acl) = &state.acl else {
+        return Err(DecryptError::AclNotConfigured);
+    };
+    let queries: Vec<AclQuery> = handles.iter().map(|h| AclQuery::AllowedForDecryption(*h)).collect();
+    match acl.first_denied(&queries).await {
+        Ok(None) => Ok(()),
+        Ok(Some(denied)) => {
+            println!("[decrypt] ACL denied {:?}", denied);
+            Err(DecryptError::AclDenied(denied.handle()))
+        }
+        Err(e) => {
+            println!("[decrypt] ACL query failed: {}", e);
+            Err(DecryptError::AclUnavailable)
+        }
+    }
+}
+
+// Audit outcome of a refused decryption
+pub fn outcome(e: &DecryptError) -> &'static str {
+    match e {
+        DecryptError::EmptyRequest => "empty request",
+        DecryptError::AclNotConfigured => "acl not configured",
+        DecryptError::AclDenied(_) => "acl denied",
+        DecryptError::AclUnavailable => "acl unavailable",
+        DecryptError::CiphertextNotFound(_) => "ciphertext not found",
+        DecryptError::CoprocessorUnavailable => "coprocessor unavailable",
+        DecryptError::KeysetMismatch { .. } => "keyset mismatch",
+        DecryptError::Auth(AuthError::NonceReused(_)) => "nonce reused",
+        DecryptError::Auth(_) => "unauthorized",
+        DecryptError::Kms(KmsError::InvalidPublicKey) => "invalid public key",
+        _ => "failed",
+    }
+}
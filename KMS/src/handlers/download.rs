@@ -0,0 +1,199 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use crate::error::KmsError;
+use crate::state::KmsState;
+
+// Binary counterparts of /keys/public, /keys/server and /keys/crs, plus the compressed
+// server key which is only served this way
+// The bincode serialized key is streamed from the key store as application/octet-stream.
+// The ETag is the sha256 of the key, so If-None-Match revalidates and Range / If-Range
+// resume an interrupted download. Keyset id and preset come in X-Key-Id and X-Key-Params.
+
+pub async fn public_key(State(state): State<KmsState>, headers: HeaderMap) -> Response {
+    download(&state, None, "public_key", &headers).await
+}
+
+pub async fn keyset_public_key(
+    State(state): State<KmsState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    download(&state, Some(&id), "public_key", &headers).await
+}
+
+pub async fn server_key(State(state): State<KmsState>, headers: HeaderMap) -> Response {
+    download(&state, None, "server_key", &headers).await
+}
+
+pub async fn keyset_server_key(
+    State(state): State<KmsState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    download(&state, Some(&id), "server_key", &headers).await
+}
+
+pub async fn compressed_server_key(State(state): State<KmsState>, headers: HeaderMap) -> Response {
+    download(&state, None, "compressed_server_key", &headers).await
+}
+
+pub async fn keyset_compressed_server_key(
+    State(state): State<KmsState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    download(&state, Some(&id), "compressed_server_key", &headers).await
+}
+
+pub async fn crs(State(state): State<KmsState>, headers: HeaderMap) -> Response {
+    download(&state, None, "crs", &headers).await
+}
+
+pub async fn keyset_crs(
+    State(state): State<KmsState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    download(&state, Some(&id), "crs", &headers).await
+}
+
+async fn download(state: &KmsState, id: Option<&str>, name: &str, request: &HeaderMap) -> Response {
+    let result = async {
+        let keyset = state.kms_service.keyset(id).await?;
+        let (digest, entry) = state.kms_service.open_key(&keyset, name).await?;
+        anyhow::Ok((keyset, digest, entry))
+    }
+    .await;
+    let (keyset, digest, mut entry) = match result {
+        Ok(found) => found,
+        Err(e) => return KmsError::from(e).into_response(),
+    };
+
+    let etag = format!("\"{}\"", digest.sha256);
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+    let disposition = format!("attachment; filename=\"{}-{}.bin\"", keyset.metadata.id, name);
+    headers.insert(header::CONTENT_DISPOSITION, HeaderValue::from_str(&disposition).unwrap());
+    headers.insert("x-key-id", HeaderValue::from_str(&keyset.metadata.id).unwrap());
+    headers.insert("x-key-params", HeaderValue::from_str(&keyset.metadata.params).unwrap());
+
+    if etag_matches(request.get(header::IF_NONE_MATCH), &etag) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    // A Range only applies to the representation the client already has part of
+    let range = match request.get(header::RANGE) {
+        Some(range) if if_range_holds(request.get(header::IF_RANGE), &etag) => {
+            parse_range(range.to_str().unwrap_or_default(), entry.len)
+        }
+        _ => Ok(None),
+    };
+    let (status, start, end) = match range {
+        Ok(Some((start, end))) => {
+            let content_range = format!("bytes {}-{}/{}", start, end, entry.len);
+            headers.insert(header::CONTENT_RANGE, HeaderValue::from_str(&content_range).unwrap());
+            (StatusCode::PARTIAL_CONTENT, start, end)
+        }
+        Ok(None) if entry.len == 0 => return (StatusCode::OK, headers).into_response(),
+        Ok(None) => (StatusCode::OK, 0, entry.len - 1),
+        Err(()) => {
+            let content_range = format!("bytes */{}", entry.len);
+            headers.insert(header::CONTENT_RANGE, HeaderValue::from_str(&content_range).unwrap());
+            return (StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response();
+        }
+    };
+
+    if start > 0 && entry.reader.seek(SeekFrom::Start(start)).await.is_err() {
+        return KmsError::Storage(format!("seeking {} failed", name)).into_response();
+    }
+    let len = end - start + 1;
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+    let body = Body::from_stream(ReaderStream::new(entry.reader.take(len)));
+    (status, headers, body).into_response()
+}
+
+fn etag_matches(if_none_match: Option<&HeaderValue>, etag: &str) -> bool {
+    let Some(value) = if_none_match.and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    value
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+// Without If-Range the Range always applies, with it only while the key is unchanged
+fn if_range_holds(if_range: Option<&HeaderValue>, etag: &str) -> bool {
+    match if_range {
+        None => true,
+        Some(value) => value.to_str().map(|v| v.trim() == etag).unwrap_or(false),
+    }
+}
+
+// Parse a single `bytes=` range into inclusive bounds within `len`
+// Ok(None) means serve the whole key: another unit or several ranges, which we don't do
+// Err means the range lies outside the key (416)
+fn parse_range(range: &str, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((first, last)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let bounds = if first.is_empty() {
+        // bytes=-N, the last N bytes
+        match last.parse::<u64>() {
+            Ok(0) | Err(_) => return Err(()),
+            Ok(suffix) => (len.saturating_sub(suffix), len.checked_sub(1).ok_or(())?),
+        }
+    } else {
+        match (first.parse::<u64>(), last.parse::<u64>()) {
+            // bytes=N-
+            (Ok(start), _) if last.is_empty() => (start, len.checked_sub(1).ok_or(())?),
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+            _ => return Ok(None),
+        }
+    };
+    if bounds.0 >= len {
+        return Err(());
+    }
+    Ok(Some(bounds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some((0, 99))));
+        assert_eq!(parse_range("bytes=900-", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some((900, 999))));
+        // Ends past the key are clamped, starts past it can't be served
+        assert_eq!(parse_range("bytes=500-5000", 1000), Ok(Some((500, 999))));
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(parse_range("bytes=-0", 1000), Err(()));
+        // Multiple ranges, other units and malformed ranges get the whole key
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Ok(None));
+        assert_eq!(parse_range("items=0-1", 1000), Ok(None));
+        assert_eq!(parse_range("bytes=9-1", 1000), Ok(None));
+
+        assert!(etag_matches(Some(&HeaderValue::from_static("\"a\", W/\"b\"")), "\"b\""));
+        assert!(!etag_matches(Some(&HeaderValue::from_static("\"a\"")), "\"b\""));
+        assert!(if_range_holds(None, "\"b\""));
+        assert!(!if_range_holds(Some(&HeaderValue::from_static("\"a\"")), "\"b\""));
+    }
+}
@@ -1,51 +1,295 @@
-use axum::{extract::State, http::StatusCode, Json};
-use base64::engine::general_purpose::STANDARD as BASE64;
-use base64::Engine;
-use serde::Serialize;
+use std::net::SocketAddr;
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use crate::audit::AuditEvent;
+use crate::jobs::Job;
+use crate::error::KmsError;
+use crate::kms::{KeyDigest, KeysetMetadata};
+use crate::params::{self, ParamPreset, PresetError};
 use crate::state::KmsState;
 
 #[derive(Serialize)]
 pub struct PublicKeyResponse {
-    pub public_key: String,
+    pub key_id: String,
+    // Parameter preset of the keyset, see GET /params/{name}
+    pub params: String,
+    pub public_key: Arc<str>,
 }
 
 #[derive(Serialize)]
 pub struct ServerKeyResponse {
-    pub server_key: String,
+    pub key_id: String,
+    pub params: String,
+    pub server_key: Arc<str>,
 }
 
-pub async fn generate(State(state): State<KmsState>) -> Result<Json<&'static str>, StatusCode> {
-    state
-        .kms_service
-        .generate_and_store()
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    Ok(Json("Keys generated and stored successfully"))
+#[derive(Serialize)]
+pub struct CrsResponse {
+    pub key_id: String,
+    pub params: String,
+    // base64 of the CRS public params serialized with tfhe::safe_serialization::safe_serialize
+    pub crs: Arc<str>,
+}
+
+// What clients pin: size and fingerprints of each downloadable key of a keyset
+#[derive(Serialize)]
+pub struct KeyInfoResponse {
+    pub key_id: String,
+    pub params: String,
+    pub keys: BTreeMap<&'static str, KeyDigest>,
+}
+
+#[derive(Deserialize)]
+pub struct GenerateParams {
+    // Generate even though a keyset exists
+    #[serde(default)]
+    pub force: bool,
+    // Parameter preset, TFHE_PARAMS when omitted
+    #[serde(default)]
+    pub params: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RotateParams {
+    #[serde(default)]
+    pub params: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenerateOutcome {
+    // A new keyset is being generated
+    Created,
+    // A generation was already running, this call joined it
+    Joined,
+    // A keyset exists and no forced generation was asked for, nothing changed
+    Kept,
+}
+
+#[derive(Serialize)]
+pub struct GenerateResponse {
+    pub outcome: GenerateOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<Job>,
+    // Active keyset, when it was kept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyset: Option<KeysetMetadata>,
+}
+
+#[derive(Serialize)]
+pub struct KeysetsResponse {
+    pub active: Option<String>,
+    pub keysets: Vec<KeysetMetadata>,
+}
+
+// Key generation takes minutes, so it runs as a job polled through GET /jobs/{id}
+// Once a keyset exists this is a no-op unless `?force=true` is given, in which case
+// the new keyset replaces the active one (which gets archived)
+pub async fn generate(
+    State(state): State<KmsState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<GenerateParams>,
+) -> Result<(StatusCode, Json<GenerateResponse>), KmsError> {
+    let exists = !state.kms_service.list().await.is_empty();
+    if exists && !params.force {
+        let keyset = state.kms_service.keyset(None).await.ok();
+        audit_admin(&state, addr, "keys_generate", "kept").await;
+        return Ok((
+            StatusCode::OK,
+            Json(GenerateResponse {
+                outcome: GenerateOutcome::Kept,
+                job: None,
+                keyset: keyset.map(|k| k.metadata.clone()),
+            }),
+        ));
+    }
+    let result = resolve_params(&state, params.params.as_deref())
+        .map_err(KmsError::from)
+        .and_then(|preset| start_keygen(&state, exists, preset));
+    audit_admin(&state, addr, "keys_generate", outcome(&result)).await;
+    result
+}
+
+// Generate a keyset and make it active once ready, older keysets stay available for decryption
+pub async fn rotate(
+    State(state): State<KmsState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<RotateParams>,
+) -> Result<(StatusCode, Json<GenerateResponse>), KmsError> {
+    let result = resolve_params(&state, params.params.as_deref())
+        .map_err(KmsError::from)
+        .and_then(|preset| start_keygen(&state, true, preset));
+    audit_admin(&state, addr, "keys_rotate", outcome(&result)).await;
+    result
+}
+
+fn resolve_params(state: &KmsState, name: Option<&str>) -> Result<&'static ParamPreset, PresetError> {
+    match name {
+        Some(name) => params::generation_preset(name),
+        None => Ok(state.default_params),
+    }
+}
+
+fn start_keygen(
+    state: &KmsState,
+    activate: bool,
+    preset: &'static ParamPreset,
+) -> Result<(StatusCode, Json<GenerateResponse>), KmsError> {
+    let (job, started) = state.jobs.start_keygen(state.kms_service.clone(), activate, preset)?;
+    let outcome = if started { GenerateOutcome::Created } else { GenerateOutcome::Joined };
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(GenerateResponse {
+            outcome,
+            job: Some(job),
+            keyset: None,
+        }),
+    ))
+}
+
+// Outcome recorded for a generation request, the error code when it was refused
+fn outcome(result: &Result<(StatusCode, Json<GenerateResponse>), KmsError>) -> &'static str {
+    match result {
+        Ok((_, response)) => match response.outcome {
+            GenerateOutcome::Created => "started",
+            GenerateOutcome::Joined => "joined",
+            GenerateOutcome::Kept => "kept",
+        },
+        Err(e) => e.code(),
+    }
+}
+
+// Key changes are admin-only routes (see rbac), denials are audited there and the
+// admin actions that got past it here, with how they ended
+async fn audit_admin(state: &KmsState, addr: SocketAddr, action: &'static str, outcome: &'static str) {
+    let event = AuditEvent {
+        action,
+        caller: addr.to_string(),
+        handles: vec![],
+        outcome,
+    };
+    state.audit.record(event).await;
+}
+
+pub async fn job(State(state): State<KmsState>, Path(id): Path<u64>) -> Result<Json<Job>, KmsError> {
+    state.jobs.get(id).map(Json).ok_or(KmsError::JobNotFound(id))
 }
 
-pub async fn public_key(State(state): State<KmsState>) -> Result<Json<PublicKeyResponse>, StatusCode> {
-    let public_key = state
-        .kms_service
-        .load_public()
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let bytes = bincode::serialize(&public_key).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+pub async fn list(State(state): State<KmsState>) -> Json<KeysetsResponse> {
+    Json(KeysetsResponse {
+        active: state.kms_service.active_id().await,
+        keysets: state.kms_service.list().await,
+    })
+}
+
+pub async fn active(State(state): State<KmsState>) -> Result<Json<KeysetMetadata>, KmsError> {
+    let keyset = state.kms_service.keyset(None).await?;
+    Ok(Json(keyset.metadata.clone()))
+}
+
+pub async fn activate(
+    State(state): State<KmsState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(id): Path<String>,
+) -> Result<Json<KeysetMetadata>, KmsError> {
+    let result = state.kms_service.activate(&id).await.map_err(KmsError::from);
+    let outcome = result.as_ref().map_or_else(|e| e.code(), |_| "ok");
+    audit_admin(&state, addr, "keys_activate", outcome).await;
+    result?;
+    active(State(state)).await
+}
+
+pub async fn info(State(state): State<KmsState>) -> Result<Json<KeyInfoResponse>, KmsError> {
+    info_of(&state, None).await
+}
+
+pub async fn keyset_info(
+    State(state): State<KmsState>,
+    Path(id): Path<String>,
+) -> Result<Json<KeyInfoResponse>, KmsError> {
+    info_of(&state, Some(&id)).await
+}
+
+async fn info_of(state: &KmsState, id: Option<&str>) -> Result<Json<KeyInfoResponse>, KmsError> {
+    let keyset = state.kms_service.keyset(id).await?;
+    let keys = state.kms_service.key_digests(&keyset).await?;
+
+    Ok(Json(KeyInfoResponse {
+        key_id: keyset.metadata.id.clone(),
+        params: keyset.metadata.params.clone(),
+        keys,
+    }))
+}
+
+pub async fn public_key(State(state): State<KmsState>) -> Result<Json<PublicKeyResponse>, KmsError> {
+    public_key_of(&state, None).await
+}
+
+pub async fn keyset_public_key(
+    State(state): State<KmsState>,
+    Path(id): Path<String>,
+) -> Result<Json<PublicKeyResponse>, KmsError> {
+    public_key_of(&state, Some(&id)).await
+}
+
+pub async fn server_key(State(state): State<KmsState>) -> Result<Json<ServerKeyResponse>, KmsError> {
+    server_key_of(&state, None).await
+}
+
+pub async fn keyset_server_key(
+    State(state): State<KmsState>,
+    Path(id): Path<String>,
+) -> Result<Json<ServerKeyResponse>, KmsError> {
+    server_key_of(&state, Some(&id)).await
+}
+
+async fn public_key_of(state: &KmsState, id: Option<&str>) -> Result<Json<PublicKeyResponse>, KmsError> {
+    let keyset = state.kms_service.keyset(id).await?;
+    let public_key = keyset.public_key().await?;
 
     Ok(Json(PublicKeyResponse {
-        public_key: BASE64.encode(&bytes),
+        key_id: keyset.metadata.id.clone(),
+        params: keyset.metadata.params.clone(),
+        public_key: public_key.encoded.clone(),
     }))
 }
 
-pub async fn server_key(State(state): State<KmsState>) -> Result<Json<ServerKeyResponse>, StatusCode> {
-    let server_key = state
-        .kms_service
-        .load_server()
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let bytes = bincode::serialize(&server_key).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+async fn server_key_of(state: &KmsState, id: Option<&str>) -> Result<Json<ServerKeyResponse>, KmsError> {
+    let keyset = state.kms_service.keyset(id).await?;
+    let server_key = keyset.server_key().await?;
 
     Ok(Json(ServerKeyResponse {
-        server_key: BASE64.encode(&bytes),
+        key_id: keyset.metadata.id.clone(),
+        params: keyset.metadata.params.clone(),
+        server_key: server_key.encoded.clone(),
     }))
-}
\ No newline at end of file
+}
+
+pub async fn crs(State(state): State<KmsState>) -> Result<Json<CrsResponse>, KmsError> {
+    crs_of(&state, None).await
+}
+
+pub async fn keyset_crs(
+    State(state): State<KmsState>,
+    Path(id): Path<String>,
+) -> Result<Json<CrsResponse>, KmsError> {
+    crs_of(&state, Some(&id)).await
+}
+
+// Keysets generated before CRS generation existed have none, that is a 404
+async fn crs_of(state: &KmsState, id: Option<&str>) -> Result<Json<CrsResponse>, KmsError> {
+    let keyset = state.kms_service.keyset(id).await?;
+    let crs = keyset.crs().await?;
+
+    Ok(Json(CrsResponse {
+        key_id: keyset.metadata.id.clone(),
+        params: keyset.metadata.params.clone(),
+        crs: crs.encoded.clone(),
+    }))
+}
@@ -1,2 +1,8 @@
+pub mod decrypt;
+pub mod download;
 pub mod health;
 pub mod keys;
+pub mod params;
+pub mod peer;
+pub mod signer;
+pub mod user_decrypt;
@@ -0,0 +1,31 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use serde::Serialize;
+use crate::error::error_response;
+use crate::params::{self, ParamPreset, PRESETS};
+use crate::state::KmsState;
+
+#[derive(Serialize)]
+pub struct ParamsResponse {
+    // Preset used when a generation request doesn't name one
+    pub default: &'static str,
+    pub presets: &'static [ParamPreset],
+}
+
+pub async fn list(State(state): State<KmsState>) -> Json<ParamsResponse> {
+    Json(ParamsResponse {
+        default: state.default_params.name,
+        presets: &PRESETS,
+    })
+}
+
+// An unknown name is a missing resource here, not a bad generation request
+pub async fn get(Path(name): Path<String>) -> Result<Json<&'static ParamPreset>, Response> {
+    params::preset(&name)
+        .map(Json)
+        .map_err(|e| error_response(StatusCode::NOT_FOUND, e.code(), &e.to_string()))
+}
@@ -0,0 +1,140 @@
+use std::net::SocketAddr;
+use axum::{
+    body::Bytes,
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use crate::audit::AuditEvent;
+use crate::auth::{bearer_token, AuthError};
+use crate::cluster::{ShareDelivery, ShareGrant, ShareRequest, ShareResponse};
+use crate::decryption::seal_to;
+use crate::error::{DecryptError, KmsError};
+use crate::handlers::decrypt::{authorize_public, ciphertexts, outcome};
+use crate::handlers::user_decrypt::authorize_user;
+use crate::kms::is_replicated_entry;
+use crate::shamir::Share;
+use crate::state::KmsState;
+
+// PUT /peer/entries/{*name}: keyset entries pushed by the node that generated or switched a keyset
+pub async fn put_entry(
+    State(state): State<KmsState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<StatusCode, KmsError> {
+    let audit = |outcome| AuditEvent {
+        action: "peer_entry",
+        caller: addr.to_string(),
+        handles: vec![name.clone()],
+        outcome,
+    };
+    if !is_replicated_entry(&name) {
+        state.audit.record(audit("invalid entry")).await;
+        return Err(KmsError::InvalidEntry(name));
+    }
+    if let Err(e) = state.kms_service.apply_replica(&name, &body).await {
+        println!("[KMS] failed to apply replicated {}: {:#}", name, e);
+        state.audit.record(audit("failed")).await;
+        return Err(KmsError::from(e));
+    }
+    state.audit.record(audit("stored")).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// PUT /peer/keysets/{id}/share: this node's share of a keyset another node generated
+pub async fn put_share(
+    State(state): State<KmsState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(id): Path<String>,
+    Json(delivery): Json<ShareDelivery>,
+) -> Result<StatusCode, KmsError> {
+    let audit = |outcome| AuditEvent {
+        action: "share_receive",
+        caller: format!("node {} ({})", delivery.from, addr),
+        handles: vec![id.clone()],
+        outcome,
+    };
+    if let Err(e) = state.kms_service.receive_share(&id, delivery.from, &delivery.sealed).await {
+        println!("[KMS] refused share of keyset {} from node {}: {:#}", id, delivery.from, e);
+        state.audit.record(audit("refused")).await;
+        return Err(KmsError::InvalidShare);
+    }
+    state.audit.record(audit("stored")).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// POST /peer/keysets/{id}/share: release this node's client key share, sealed to
+// the requesting node's ephemeral key, for a decryption this node authorizes itself
+pub async fn share(
+    State(state): State<KmsState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<ShareRequest>,
+) -> Result<Json<ShareResponse>, DecryptError> {
+    let audit = |outcome| AuditEvent {
+        action: "share_release",
+        caller: format!("node {} ({})", request.from, addr),
+        handles: std::iter::once(id.clone())
+            .chain(request.grant.handles().iter().map(|h| h.to_string()))
+            .collect(),
+        outcome,
+    };
+    // A node's token can't be used to collect shares on behalf of another one
+    let caller = bearer_token(&headers).and_then(|token| state.kms_service.peer_of(token));
+    if caller != Some(request.from) {
+        state.audit.record(audit("wrong peer")).await;
+        return Err(AuthError::WrongPeer(request.from).into());
+    }
+    let public_key: [u8; 32] = match BASE64.decode(&request.public_key).ok().and_then(|k| k.try_into().ok()) {
+        Some(key) => key,
+        None => {
+            state.audit.record(audit("invalid public key")).await;
+            return Err(KmsError::InvalidPublicKey.into());
+        }
+    };
+    if let Err(e) = authorize_grant(&state, &id, &request.grant).await {
+        state.audit.record(audit(outcome(&e))).await;
+        return Err(e);
+    }
+
+    let sealed = match state.kms_service.share(&id).await {
+        Ok(bytes) => Share::from_bytes(&bytes).map(|share| (share.index, seal_to(public_key, &bytes))),
+        Err(e) => Err(e),
+    };
+    match sealed {
+        Ok((index, Ok(sealed))) => {
+            state.audit.record(audit("released")).await;
+            Ok(Json(ShareResponse {
+                index,
+                sealed: BASE64.encode(sealed),
+            }))
+        }
+        Ok((_, Err(e))) | Err(e) => {
+            println!("[KMS] failed to release share of keyset {}: {:#}", id, e);
+            state.audit.record(audit("failed")).await;
+            Err(KmsError::from(e).into())
+        }
+    }
+}
+
+// Check the decryption a share is requested for as if it had been asked of this node,
+// and that its ciphertexts were computed under keyset `id`
+async fn authorize_grant(state: &KmsState, id: &str, grant: &ShareGrant) -> Result<(), DecryptError> {
+    let signed = match grant {
+        ShareGrant::Public { handles } => {
+            authorize_public(state, handles).await?;
+            None
+        }
+        ShareGrant::User(body) => Some(authorize_user(state, body).await?.1),
+    };
+    ciphertexts(state, grant.handles(), Some(id)).await?;
+    // A signed request gets one share out of every node
+    if let Some(request) = signed {
+        state.authorizer.consume(&request).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,12 @@
+use axum::{extract::State, Json};
+use serde_json::{json, Value};
+use crate::state::KmsState;
+
+// Address to register in KMSVerifier (KMS_SIGNER_ADDRESS) and the EIP-712 domain it signs under
+pub async fn signer(State(state): State<KmsState>) -> Json<Value> {
+    Json(json!({
+        "address": state.signer.address(),
+        "gateway_chain_id": state.signer.gateway_chain_id(),
+        "verifying_contract": state.signer.verifying_contract(),
+    }))
+}
@@ -0,0 +1,168 @@
+use alloy::primitives::{Address, Bytes, B256, U256};
+use axum::{
+    extract::State,
+    Json,
+};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use crate::acl::AclQuery;
+use crate::audit::AuditEvent;
+use crate::decryption::{decrypt_ciphertext, seal_to};
+use crate::eip712::UserDecryptRequest;
+use crate::cluster::ShareGrant;
+use crate::handlers::decrypt::{ciphertexts, outcome};
+use crate::error::{DecryptError, KmsError};
+use crate::state::KmsState;
+
+// Also carried to the peers, which check it again before releasing their key share
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UserDecryptBody {
+    pub handles: Vec<B256>,
+    pub contract_address: Address,
+    pub user_address: Address,
+    // X25519 public key the plaintexts are sealed to
+    pub public_key: Bytes,
+    pub nonce: U256,
+    // Unix timestamp (seconds) after which the signature is no longer accepted
+    pub expiry: u64,
+    // EIP-712 signature of the user over UserDecryptRequest
+    pub signature: Bytes,
+    // Keyset the ciphertexts are expected under, refused when the coprocessor computed
+    // them under another one
+    #[serde(default)]
+    pub key_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct UserDecryptResponse {
+    pub sealed: Vec<SealedPlaintext>,
+}
+
+#[derive(Serialize)]
+pub struct SealedPlaintext {
+    pub handle: B256,
+    // base64 of the sealed box holding the 32 byte big-endian plaintext
+    pub sealed: String,
+}
+
+// Re-encrypt handles for their owner: the plaintexts never leave the KMS unsealed
+pub async fn user_decrypt(
+    State(state): State<KmsState>,
+    Json(body): Json<UserDecryptBody>,
+) -> Result<Json<UserDecryptResponse>, DecryptError> {
+    let audit = |outcome| AuditEvent {
+        action: "decrypt_user",
+        caller: body.user_address.to_string(),
+        handles: body.handles.iter().map(|h| h.to_string()).collect(),
+        outcome,
+    };
+
+    let (public_key, request) = match authorize_user(&state, &body).await {
+        Ok(authorized) => authorized,
+        Err(e) => {
+            state.audit.record(audit(outcome(&e))).await;
+            return Err(e);
+        }
+    };
+
+    let (key_id, inputs) = match ciphertexts(&state, &body.handles, body.key_id.as_deref()).await {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            state.audit.record(audit(outcome(&e))).await;
+            return Err(e);
+        }
+    };
+
+    let grant = ShareGrant::User(body.clone());
+    let client_key = match state.kms_service.load_client(Some(&key_id), &grant).await {
+        Ok(key) => key,
+        Err(e) => {
+            println!("[decrypt_user] client key unavailable: {}", e);
+            state.audit.record(audit("client key unavailable")).await;
+            return Err(KmsError::from(e).into());
+        }
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        inputs
+            .iter()
+            .map(|(handle, ciphertext, fhe_type)| {
+                let plaintext = decrypt_ciphertext(ciphertext, *fhe_type, &client_key.key)?;
+                Ok(SealedPlaintext {
+                    handle: *handle,
+                    sealed: BASE64.encode(seal_to(public_key, &plaintext)?),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    })
+    .await
+    .map_err(|e| KmsError::Internal(e.to_string()))?;
+
+    let sealed = match result {
+        Ok(sealed) => sealed,
+        Err(e) => {
+            println!("[decrypt_user] failed: {}", e);
+            state.audit.record(audit("decryption failed")).await;
+            return Err(DecryptError::DecryptionFailed);
+        }
+    };
+    // Only a request that is answered uses up its nonce
+    if let Err(e) = state.authorizer.consume(&request).await {
+        state.audit.record(audit("nonce reused")).await;
+        return Err(e.into());
+    }
+    state.audit.record(audit("ok")).await;
+
+    Ok(Json(UserDecryptResponse { sealed }))
+}
+
+// Signature and ACL checks of a user decryption, returning the key to seal to and the
+// signed request, whose nonce is consumed once the request is answered
+// Peers run the same checks before releasing their key share for the decryption
+pub async fn authorize_user(
+    state: &KmsState,
+    body: &UserDecryptBody,
+) -> Result<([u8; 32], UserDecryptRequest), DecryptError> {
+    let Some(acl) = &state.acl else {
+        return Err(DecryptError::AclNotConfigured);
+    };
+    if body.handles.is_empty() {
+        return Err(DecryptError::EmptyRequest);
+    }
+    let Ok(public_key) = <[u8; 32]>::try_from(body.public_key.as_ref()) else {
+        return Err(KmsError::InvalidPublicKey.into());
+    };
+
+    let request = UserDecryptRequest {
+        handles: body.handles.clone(),
+        contractAddress: body.contract_address,
+        userAddress: body.user_address,
+        publicKey: body.public_key.clone(),
+        nonce: body.nonce,
+        expiry: U256::from(body.expiry),
+    };
+    state.authorizer.verify(&request, &body.signature).await?;
+
+    // Both the user and the contract holding the value must have been granted access
+    let queries: Vec<AclQuery> = body
+        .handles
+        .iter()
+        .flat_map(|handle| {
+            [
+                AclQuery::Allowed(*handle, body.user_address),
+                AclQuery::Allowed(*handle, body.contract_address),
+            ]
+        })
+        .collect();
+    match acl.first_denied(&queries).await {
+        Ok(None) => Ok((public_key, request)),
+        Ok(Some(denied)) => {
+            println!("[decrypt_user] ACL denied {:?}", denied);
+            Err(DecryptError::AclDenied(denied.handle()))
+        }
+        Err(e) => {
+            println!("[decrypt_user] ACL query failed: {}", e);
+            Err(DecryptError::AclUnavailable)
+        }
+    }
+}
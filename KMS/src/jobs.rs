@@ -0,0 +1,162 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use crate::auth::now;
+use crate::kms::KmsService;
+use crate::params::ParamPreset;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Running { stage: &'static str },
+    Succeeded { finished_at: u64, keyset_id: String },
+    Failed { finished_at: u64, error: String },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Job {
+    pub id: u64,
+    pub kind: &'static str,
+    pub started_at: u64,
+    // Whether the generated keyset becomes the active one
+    pub activate: bool,
+    // Parameter preset the keyset is generated with
+    pub params: &'static str,
+    #[serde(flatten)]
+    pub status: JobStatus,
+}
+
+// A generation was asked for while one with other settings runs, joining it would
+// silently give the caller a keyset that isn't what they asked for
+#[derive(Debug, Error)]
+#[error(
+    "key generation job {} is already running with params={} activate={}, retry once it finished",
+    .0.id, .0.params, .0.activate
+)]
+pub struct KeygenConflict(pub Job);
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    jobs: HashMap<u64, Job>,
+    // Only one key generation may run at a time
+    running_keygen: Option<u64>,
+}
+
+// Jobs tracks long running background work (key generation) so handlers return at once
+#[derive(Clone, Default)]
+pub struct Jobs {
+    inner: Arc<Mutex<Registry>>,
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        self.inner.lock().unwrap().jobs.get(&id).cloned()
+    }
+
+    // Start a key generation job, or join the one already running if it has the same settings
+    // The boolean is false when the call was merged into the running job
+    pub fn start_keygen(
+        &self,
+        kms_service: KmsService,
+        activate: bool,
+        params: &'static ParamPreset,
+    ) -> Result<(Job, bool), KeygenConflict> {
+        let mut registry = self.inner.lock().unwrap();
+        if let Some(id) = registry.running_keygen {
+            let running = registry.jobs[&id].clone();
+            if running.activate != activate || running.params != params.name {
+                return Err(KeygenConflict(running));
+            }
+            return Ok((running, false));
+        }
+        registry.next_id += 1;
+        let id = registry.next_id;
+        let job = Job {
+            id,
+            kind: "keygen",
+            started_at: now(),
+            activate,
+            params: params.name,
+            status: JobStatus::Running { stage: "queued" },
+        };
+        registry.jobs.insert(id, job.clone());
+        registry.running_keygen = Some(id);
+        drop(registry);
+
+        let jobs = self.clone();
+        tokio::spawn(async move {
+            let reporter = jobs.clone();
+            let progress = move |stage| reporter.set_status(id, JobStatus::Running { stage });
+            let result = kms_service.generate_and_store(activate, params, progress).await;
+            let finished_at = now();
+            let status = match result {
+                Ok(metadata) => JobStatus::Succeeded {
+                    finished_at,
+                    keyset_id: metadata.id,
+                },
+                Err(e) => {
+                    println!("[Jobs] keygen job {} failed: {}", id, e);
+                    JobStatus::Failed { finished_at, error: e.to_string() }
+                }
+            };
+            jobs.set_status(id, status);
+            jobs.inner.lock().unwrap().running_keygen = None;
+        });
+        println!("[Jobs] keygen job {} started", id);
+        Ok((job, true))
+    }
+
+    fn set_status(&self, id: u64, status: JobStatus) {
+        if let Some(job) = self.inner.lock().unwrap().jobs.get_mut(&id) {
+            job.status = status;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::preset;
+    use crate::store::MemoryStore;
+
+    // Pretend a keygen with `activate` is running, without generating anything
+    fn running(jobs: &Jobs, activate: bool) -> u64 {
+        let mut registry = jobs.inner.lock().unwrap();
+        registry.next_id += 1;
+        let id = registry.next_id;
+        let job = Job {
+            id,
+            kind: "keygen",
+            started_at: now(),
+            activate,
+            params: "tuniform-2m64",
+            status: JobStatus::Running { stage: "generating keys" },
+        };
+        registry.jobs.insert(id, job);
+        registry.running_keygen = Some(id);
+        id
+    }
+
+    #[tokio::test]
+    async fn test_keygen_joins_only_matching_jobs() {
+        let service = KmsService::new(Arc::new(MemoryStore::new()), None).await.unwrap();
+        let params = preset("tuniform-2m64").unwrap();
+        let jobs = Jobs::new();
+        let id = running(&jobs, false);
+
+        let (job, started) = jobs.start_keygen(service.clone(), false, params).unwrap();
+        assert_eq!((job.id, started), (id, false));
+        // A forced generation must not end up in a job that never activates
+        let conflict = jobs.start_keygen(service.clone(), true, params).unwrap_err();
+        assert_eq!(conflict.0.id, id);
+        // Nor one for another preset in a job generating tuniform-2m64 keys
+        let other = preset("compact-pke-2m64").unwrap();
+        assert!(jobs.start_keygen(service, false, other).is_err());
+    }
+}
@@ -1,55 +1,818 @@
-use anyhow::Result;
-use serde::{de::DeserializeOwned, Serialize};
-use std::path::{Path, PathBuf};
-use tfhe::{generate_keys, CompactPublicKey, ConfigBuilder, ServerKey};
-use tokio::fs;
+use alloy::primitives::Keccak256;
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use crypto_box::aead::rand_core::RngCore;
+use crypto_box::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tfhe::safe_serialization::{safe_deserialize, safe_serialize};
+use tfhe::zk::{CompactPkeCrs, CompactPkePublicParams};
+use tfhe::{ClientKey, CompactPublicKey, CompressedServerKey, ServerKey};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio::sync::RwLock;
+use zeroize::{Zeroize, Zeroizing};
+use crate::auth::now;
+use crate::cluster::{Cluster, ShareGrant};
+use crate::params::ParamPreset;
+use crate::shamir::Share;
+use crate::store::{is_secret, EntryReader, KeyStore};
+
+// Key entry names inside a keyset (and of the legacy flat layout)
+const KEY_FILES: [&str; 5] = ["client_key", "server_key", "compressed_server_key", "public_key", "crs"];
+
+// Keys of a keyset anyone may download, the ones /keys/{id}/info fingerprints
+pub const PUBLIC_KEYS: [&str; 4] = ["public_key", "server_key", "compressed_server_key", "crs"];
+
+// Bits a single proven compact list may hold, enough for one 64 bit input per list
+const CRS_MAX_BITS: usize = 64;
+
+// Upper bound on a serialized CRS read back through `safe_deserialize`
+pub const CRS_SIZE_LIMIT: u64 = 1 << 30;
+
+// This node's share of the client key, kept instead of `client_key` in threshold mode
+pub const SHARE_ENTRY: &str = "client_key_share";
+
+// Entries of a keyset that peers push to each other, shares go through receive_share
+const REPLICATED: [&str; 5] = ["server_key", "compressed_server_key", "public_key", "crs", "metadata.json"];
 
 // KmsService handles key management operations
-// Struct stores the directory path where keys are stored
+// Every generation creates a new keyset under `keysets/<id>/` of the key store,
+// `active` names the keyset used for new encryptions. Older keysets stay loadable so
+// ciphertexts made under them can still be decrypted.
 #[derive(Clone)]
 pub struct KmsService {
-    dir: PathBuf,
+    store: Arc<dyn KeyStore>,
+    keysets: Arc<RwLock<BTreeMap<String, Arc<Keyset>>>>,
+    active: Arc<RwLock<Option<String>>>,
+    // Set in threshold mode, the client key is then only held as shares across the nodes
+    cluster: Option<Arc<Cluster>>,
+    // Digests of downloaded key entries with the entry version they were computed for
+    digests: Arc<RwLock<HashMap<String, (SystemTime, KeyDigest)>>>,
+}
+
+#[derive(Debug, Error)]
+pub enum KeysetError {
+    #[error("keyset {0} not found")]
+    NotFound(String),
+    #[error("no active keyset, generate one first")]
+    NoActive,
+    #[error("{0} is missing from the key store")]
+    KeyMissing(String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeysetMetadata {
+    pub id: String,
+    // Unix timestamp (seconds)
+    pub created_at: u64,
+    // Name of the parameter preset the keys were generated with (see GET /params)
+    pub params: String,
+    // Set once the keyset was replaced as active keyset, its files are then read-only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived_at: Option<u64>,
+    // Serialized key sizes, missing for keysets generated before compressed server keys
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sizes: Option<KeySizes>,
+}
+
+// Sizes in bytes of the bincode serialized keys
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeySizes {
+    pub server_key: usize,
+    pub compressed_server_key: usize,
+    pub public_key: usize,
+    // compressed_server_key / server_key
+    pub compression_ratio: f64,
+}
+
+// Fingerprints of a stored key, taken over its serialized bytes (what /keys/*/raw serves)
+// The sha256 doubles as ETag of the binary download endpoints
+#[derive(Clone, Debug, Serialize)]
+pub struct KeyDigest {
+    // hex, without 0x
+    pub sha256: String,
+    pub keccak256: String,
+    pub size: u64,
+}
+
+// One generation of client, server and public key
+pub struct Keyset {
+    pub metadata: KeysetMetadata,
+    client_key: KeySlot<SecretClientKey>,
+    server_key: KeySlot<ServerKey>,
+    public_key: KeySlot<CompactPublicKey>,
+    // CRS for proving compact public key encryptions under `public_key`, only its public
+    // params are kept since `CompactPkeCrs` itself has no serialized form
+    crs: KeySlot<CompactPkePublicParams>,
+}
+
+// ClientKey whose secret key material is overwritten when it is dropped, tfhe's
+// own ClientKey leaves it in freed memory
+pub struct SecretClientKey(Option<ClientKey>);
+
+impl SecretClientKey {
+    pub fn new(key: ClientKey) -> Self {
+        Self(Some(key))
+    }
+}
+
+impl Deref for SecretClientKey {
+    type Target = ClientKey;
+
+    fn deref(&self) -> &ClientKey {
+        self.0.as_ref().expect("client key is only taken on drop")
+    }
+}
+
+impl Drop for SecretClientKey {
+    fn drop(&mut self) {
+        let Some(key) = self.0.take() else {
+            return;
+        };
+        let (key, compact_key, compression_key, _) = key.into_raw_parts();
+        let (mut glwe, mut lwe, _) = key.into_raw_parts().into_raw_parts();
+        glwe.as_mut().zeroize();
+        lwe.as_mut().zeroize();
+        if let Some((compact_key, _)) = compact_key {
+            let (mut lwe, _) = compact_key.into_raw_parts().into_raw_parts();
+            lwe.as_mut().zeroize();
+        }
+        if let Some(compression_key) = compression_key {
+            compression_key.into_raw_parts().post_packing_ks_key.as_mut().zeroize();
+        }
+    }
+}
+
+// A key as loaded from disk
+pub struct LoadedKey<T> {
+    pub key: T,
+    // base64 of the serialized key, what /keys/* serve (left empty for the client key)
+    // Shared, a response only bumps the reference count instead of copying the string
+    pub encoded: Arc<str>,
+    modified: SystemTime,
+}
+
+// KeySlot caches one key entry, reloading it when the entry's version changes
+// Serialized bytes only live in zeroized buffers while the key is being (de)serialized
+struct KeySlot<T> {
+    store: Arc<dyn KeyStore>,
+    name: String,
+    encode: bool,
+    loaded: RwLock<Option<Arc<LoadedKey<T>>>>,
 }
 
 impl KmsService {
-    pub async fn new(dir: PathBuf) -> Result<Self> {
-        fs::create_dir_all(&dir).await?;
-        println!("[KmsService] init, dir: {:?}", dir);
-        Ok(Self { dir })
-    }
-
-    pub async fn generate_and_store(&self) -> Result<()> {
-        let config = ConfigBuilder::default().build();
-        let (client_key, server_key) = generate_keys(config);
-        let public_key = CompactPublicKey::new(&client_key);
-        save(&self.dir, "client_key", &client_key).await?;
-        save(&self.dir, "server_key", &server_key).await?;
-        save(&self.dir, "public_key", &public_key).await?;
-        println!("[KmsService] keys generated and stored");
+    pub async fn new(store: Arc<dyn KeyStore>, cluster: Option<Arc<Cluster>>) -> Result<Self> {
+        println!("[KmsService] init, store: {}", store.describe());
+        let service = Self {
+            store,
+            keysets: Arc::new(RwLock::new(BTreeMap::new())),
+            active: Arc::new(RwLock::new(None)),
+            cluster,
+            digests: Arc::new(RwLock::new(HashMap::new())),
+        };
+        service.import_legacy().await?;
+        service.load_keysets().await?;
+
+        // Warm the cache with the active keyset, missing keys are simply loaded on first use
+        if let Ok(keyset) = service.keyset(None).await {
+            if service.cluster.is_none() {
+                let _ = keyset.client_key().await;
+            }
+            let _ = keyset.server_key().await;
+            let _ = keyset.public_key().await;
+        }
+        Ok(service)
+    }
+
+    // Generate a fresh keyset with `params` on the blocking pool, reporting each stage to `progress`
+    // The new keyset becomes active when `activate` is set or when there is no active keyset yet
+    pub async fn generate_and_store<F>(
+        &self,
+        activate: bool,
+        params: &'static ParamPreset,
+        progress: F,
+    ) -> Result<KeysetMetadata>
+    where
+        F: Fn(&'static str) + Clone + Send + 'static,
+    {
+        let report = progress.clone();
+        let keys = tokio::task::spawn_blocking(move || -> Result<_> {
+            report("generating client key");
+            let client_key = SecretClientKey::new(ClientKey::generate(params.config()));
+            // The compressed key is generated first, the full key is its decompression
+            report("generating compressed server key");
+            let compressed_server_key = CompressedServerKey::new(&client_key);
+            report("decompressing server key");
+            let server_key = compressed_server_key.decompress();
+            report("deriving public key");
+            let public_key = CompactPublicKey::new(&client_key);
+            report("generating CRS");
+            let crs = CompactPkeCrs::from_config(params.config(), CRS_MAX_BITS)?
+                .public_params()
+                .clone();
+            Ok((client_key, server_key, compressed_server_key, public_key, crs))
+        })
+        .await??;
+        let (client_key, server_key, compressed_server_key, public_key, crs) = keys;
+
+        progress("storing keys");
+        let metadata = KeysetMetadata {
+            id: new_keyset_id(),
+            created_at: now(),
+            params: params.name.to_string(),
+            archived_at: None,
+            sizes: None,
+        };
+        let mut keyset = Keyset::new(&self.store, metadata);
+        let server_key = keyset.server_key.store(server_key).await?;
+        // Only ever downloaded as raw bytes, so it is written out but not cached
+        let compressed = bincode::serialize(&compressed_server_key)?;
+        drop(compressed_server_key);
+        let name = keyset_entry(&keyset.metadata.id, "compressed_server_key");
+        self.store.write(&name, &compressed).await?;
+        let compressed_server_key = compressed.len();
+        let public_key = keyset.public_key.store(public_key).await?;
+        keyset.crs.store(crs).await?;
+        keyset.metadata.sizes = Some(KeySizes {
+            server_key,
+            compressed_server_key,
+            public_key,
+            compression_ratio: compressed_server_key as f64 / server_key.max(1) as f64,
+        });
+        let metadata = keyset.metadata.clone();
+        match &self.cluster {
+            None => {
+                keyset.client_key.store(client_key).await?;
+            }
+            Some(cluster) => {
+                progress("distributing key shares");
+                let secret = Zeroizing::new(bincode::serialize(&*client_key)?);
+                let own = cluster.distribute(&metadata.id, &secret).await?;
+                self.store.write(&keyset_entry(&metadata.id, SHARE_ENTRY), &own.to_bytes()).await?;
+                for name in PUBLIC_KEYS {
+                    self.publish(&keyset_entry(&metadata.id, name)).await?;
+                }
+            }
+        }
+        // Metadata goes last, a keyset without it is an aborted generation
+        self.write_metadata(&metadata).await?;
+        self.keysets.write().await.insert(metadata.id.clone(), Arc::new(keyset));
+        println!("[KmsService] keyset {} generated and stored", metadata.id);
+
+        if activate || self.active.read().await.is_none() {
+            self.activate(&metadata.id).await?;
+        }
+        Ok(metadata)
+    }
+
+    // Make `id` the keyset handed out for new encryptions
+    // The keyset it replaces is archived first
+    pub async fn activate(&self, id: &str) -> Result<()> {
+        if !self.keysets.read().await.contains_key(id) {
+            return Err(KeysetError::NotFound(id.to_string()).into());
+        }
+        let mut active = self.active.write().await;
+        if let Some(previous) = active.as_deref()
+            && previous != id
+        {
+            self.archive(previous).await?;
+        }
+        self.store.write("active", id.as_bytes()).await?;
+        self.publish("active").await?;
+        *active = Some(id.to_string());
+        println!("[KmsService] active keyset is now {}", id);
+        Ok(())
+    }
+
+    // Stamp a keyset as archived and protect its key entries (read-only files), so it
+    // can still decrypt old ciphertexts but nothing overwrites it by accident
+    async fn archive(&self, id: &str) -> Result<()> {
+        let mut keysets = self.keysets.write().await;
+        let keyset = keysets.get(id).ok_or_else(|| KeysetError::NotFound(id.to_string()))?;
+        if keyset.metadata.archived_at.is_some() {
+            return Ok(());
+        }
+        let mut metadata = keyset.metadata.clone();
+        metadata.archived_at = Some(now());
+
+        self.write_metadata(&metadata).await?;
+        self.protect(id).await?;
+        keysets.insert(id.to_string(), Arc::new(Keyset::new(&self.store, metadata)));
+        println!("[KmsService] keyset {} archived", id);
+        Ok(())
+    }
+
+    pub async fn active_id(&self) -> Option<String> {
+        self.active.read().await.clone()
+    }
+
+    pub async fn list(&self) -> Vec<KeysetMetadata> {
+        let keysets = self.keysets.read().await;
+        keysets.values().map(|k| k.metadata.clone()).collect()
+    }
+
+    // Keyset `id`, or the active keyset when `id` is None
+    pub async fn keyset(&self, id: Option<&str>) -> Result<Arc<Keyset>> {
+        let id = match id {
+            Some(id) => id.to_string(),
+            None => self.active_id().await.ok_or(KeysetError::NoActive)?,
+        };
+        let keysets = self.keysets.read().await;
+        Ok(keysets.get(&id).cloned().ok_or(KeysetError::NotFound(id))?)
+    }
+
+    // Client key of keyset `id` (the active one when None) for the decryption `grant`
+    // In threshold mode the key is rebuilt from the peers' shares on every call and
+    // never cached, it is gone once the caller drops it. Peers check `grant` themselves
+    // before handing over their share.
+    pub async fn load_client(&self, id: Option<&str>, grant: &ShareGrant) -> Result<Arc<LoadedKey<SecretClientKey>>> {
+        let keyset = self.keyset(id).await?;
+        let Some(cluster) = &self.cluster else {
+            return keyset.client_key().await;
+        };
+        let own = Share::from_bytes(&self.share(&keyset.metadata.id).await?)?;
+        let secret = cluster.collect(&keyset.metadata.id, own, grant).await?;
+        let key = tokio::task::spawn_blocking(move || SecretClientKey::decode(&secret)).await??;
+        Ok(Arc::new(LoadedKey {
+            key,
+            encoded: Arc::from(""),
+            modified: SystemTime::now(),
+        }))
+    }
+
+    // Cluster node calling with the peer token `token`
+    pub fn peer_of(&self, token: &str) -> Option<u8> {
+        self.cluster.as_ref()?.peer_of(token)
+    }
+
+    // This node's share of the client key of keyset `id`
+    pub async fn share(&self, id: &str) -> Result<Zeroizing<Vec<u8>>> {
+        let share = self.store.read(&keyset_entry(id, SHARE_ENTRY)).await?;
+        Ok(share.ok_or_else(|| KeysetError::NotFound(id.to_string()))?)
+    }
+
+    // Stream the serialized key `name` (server_key, public_key, ...) of a keyset along with its digest
+    pub async fn open_key(&self, keyset: &Keyset, name: &str) -> Result<(KeyDigest, EntryReader)> {
+        if is_secret(name) {
+            bail!("{} can't be downloaded", name);
+        }
+        let entry = keyset_entry(&keyset.metadata.id, name);
+        let digest = self.digest(&entry).await?;
+        let reader = self
+            .store
+            .open(&entry)
+            .await?
+            .ok_or(KeysetError::KeyMissing(entry))?;
+        Ok((digest, reader))
+    }
+
+    // Fingerprints of the public keys of a keyset, keys it doesn't have are left out
+    pub async fn key_digests(&self, keyset: &Keyset) -> Result<BTreeMap<&'static str, KeyDigest>> {
+        let mut digests = BTreeMap::new();
+        for name in PUBLIC_KEYS {
+            match self.digest(&keyset_entry(&keyset.metadata.id, name)).await {
+                Ok(digest) => {
+                    digests.insert(name, digest);
+                }
+                Err(e) if matches!(e.downcast_ref(), Some(KeysetError::KeyMissing(_))) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(digests)
+    }
+
+    // Fingerprints of an entry, hashed in chunks and cached until the entry is rewritten
+    async fn digest(&self, name: &str) -> Result<KeyDigest> {
+        let missing = || KeysetError::KeyMissing(name.to_string());
+        let version = self.store.version(name).await?.ok_or_else(missing)?;
+        if let Some((computed_for, digest)) = self.digests.read().await.get(name)
+            && *computed_for == version
+        {
+            return Ok(digest.clone());
+        }
+
+        let mut entry = self.store.open(name).await?.ok_or_else(missing)?;
+        let mut sha256 = Sha256::new();
+        let mut keccak256 = Keccak256::new();
+        let mut chunk = vec![0u8; 64 * 1024];
+        loop {
+            let n = entry.reader.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            sha256.update(&chunk[..n]);
+            keccak256.update(&chunk[..n]);
+        }
+        let digest = KeyDigest {
+            sha256: hex::encode(sha256.finalize()),
+            keccak256: hex::encode(keccak256.finalize()),
+            size: entry.len,
+        };
+        self.digests.write().await.insert(name.to_string(), (version, digest.clone()));
+        Ok(digest)
+    }
+
+    // Keep the share of keyset `id` that node `from` generated for this node
+    // A share is written once, a later push can't replace it
+    pub async fn receive_share(&self, id: &str, from: u8, sealed: &str) -> Result<()> {
+        let cluster = self.cluster.as_ref().ok_or_else(|| anyhow!("this node is not part of a cluster"))?;
+        if !is_keyset_id(id) {
+            bail!("invalid keyset id {:?}", id);
+        }
+        let share = cluster.open_share(from, id, sealed).await?;
+        let name = keyset_entry(id, SHARE_ENTRY);
+        if self.store.version(&name).await?.is_some() {
+            bail!("a share of keyset {} is already stored", id);
+        }
+        self.store.write(&name, &share.to_bytes()).await
+    }
+
+    // Store an entry pushed by the peer that generated or switched a keyset
+    // Like shares, key entries are written once: a keyset's keys can't be replaced once
+    // stored, and not at all after its metadata arrived. Metadata itself only changes
+    // when the keyset is archived.
+    pub async fn apply_replica(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        if name == "active" {
+            let id = String::from_utf8_lossy(bytes).trim().to_string();
+            if !self.keysets.read().await.contains_key(&id) {
+                return Err(KeysetError::NotFound(id).into());
+            }
+            self.store.write(name, id.as_bytes()).await?;
+            *self.active.write().await = Some(id);
+            return Ok(());
+        }
+
+        let id = name.split('/').nth(1).unwrap_or_default();
+        let existing = self.keysets.read().await.get(id).map(|k| k.metadata.clone());
+        if !name.ends_with("/metadata.json") {
+            if existing.is_some() || self.store.version(name).await?.is_some() {
+                bail!("{} is already stored", name);
+            }
+            return self.store.write(name, bytes).await;
+        }
+
+        let metadata: KeysetMetadata = serde_json::from_slice(bytes)?;
+        if metadata.id != id {
+            bail!("{} holds the metadata of keyset {}", name, metadata.id);
+        }
+        if let Some(existing) = existing {
+            let mut archived = existing.clone();
+            archived.archived_at = metadata.archived_at;
+            if existing.archived_at.is_some()
+                || metadata.archived_at.is_none()
+                || serde_json::to_value(&archived)? != serde_json::to_value(&metadata)?
+            {
+                bail!("keyset {} already exists, only archiving it may change its metadata", id);
+            }
+        }
+        self.store.write(name, bytes).await?;
+        if metadata.archived_at.is_some() {
+            self.protect(&metadata.id).await?;
+        }
+        let keyset = Arc::new(Keyset::new(&self.store, metadata));
+        self.keysets.write().await.insert(keyset.metadata.id.clone(), keyset);
         Ok(())
     }
 
-    pub async fn load_public(&self) -> Result<CompactPublicKey> {
-        let public_key: CompactPublicKey = load(&self.dir, "public_key").await?;
-        println!("[KmsService] public key loaded");
-        Ok(public_key)
+    // Push an entry to the peers, nothing to do on a standalone node
+    async fn publish(&self, name: &str) -> Result<()> {
+        let Some(cluster) = &self.cluster else {
+            return Ok(());
+        };
+        let bytes = self
+            .store
+            .read(name)
+            .await?
+            .ok_or_else(|| anyhow!("{} is missing from the key store", name))?;
+        cluster.replicate(name, &bytes).await
+    }
+
+    async fn protect(&self, id: &str) -> Result<()> {
+        for name in KEY_FILES.into_iter().chain([SHARE_ENTRY]) {
+            let name = keyset_entry(id, name);
+            if self.store.version(&name).await?.is_some() {
+                self.store.protect(&name).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn write_metadata(&self, metadata: &KeysetMetadata) -> Result<()> {
+        let name = keyset_entry(&metadata.id, "metadata.json");
+        self.store.write(&name, &serde_json::to_vec_pretty(metadata)?).await?;
+        self.publish(&name).await
+    }
+
+    async fn load_keysets(&self) -> Result<()> {
+        let mut keysets = self.keysets.write().await;
+        for id in self.store.list("keysets").await? {
+            let Some(raw) = self.store.read(&keyset_entry(&id, "metadata.json")).await? else {
+                println!("[KmsService] skipping incomplete keyset {}", id);
+                continue;
+            };
+            let metadata: KeysetMetadata = serde_json::from_slice(&raw)
+                .with_context(|| format!("invalid metadata in keyset {}", id))?;
+            keysets.insert(metadata.id.clone(), Arc::new(Keyset::new(&self.store, metadata)));
+        }
+
+        let active = match self.store.read("active").await? {
+            Some(raw) => {
+                let id = String::from_utf8_lossy(&raw).trim().to_string();
+                if keysets.contains_key(&id) {
+                    Some(id)
+                } else {
+                    println!("[KmsService] active keyset {} does not exist, ignoring", id);
+                    None
+                }
+            }
+            None => None,
+        };
+        println!("[KmsService] {} keyset(s) loaded, active: {:?}", keysets.len(), active);
+        *self.active.write().await = active;
+        Ok(())
+    }
+
+    // Move keys of the old flat layout (`client_key`, ... at the store root) into a keyset of their own
+    async fn import_legacy(&self) -> Result<()> {
+        let Some(modified) = self.store.version("client_key").await? else {
+            return Ok(());
+        };
+        let created_at = modified
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        // Legacy keys were generated with `ConfigBuilder::default()`, the "default" preset
+        let metadata = KeysetMetadata {
+            id: new_keyset_id(),
+            created_at,
+            params: "default".to_string(),
+            archived_at: None,
+            sizes: None,
+        };
+        for name in KEY_FILES {
+            if let Some(bytes) = self.store.read(name).await? {
+                self.store.write(&keyset_entry(&metadata.id, name), &bytes).await?;
+            }
+        }
+        // The flat layout predates compressed server keys, which is what coprocessors download
+        if self.store.version("compressed_server_key").await?.is_none() {
+            let client_key = self
+                .store
+                .read("client_key")
+                .await?
+                .ok_or_else(|| KeysetError::KeyMissing("client_key".to_string()))?;
+            let compressed = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+                let client_key = SecretClientKey::decode(&client_key)?;
+                Ok(bincode::serialize(&CompressedServerKey::new(&client_key))?)
+            })
+            .await??;
+            self.store
+                .write(&keyset_entry(&metadata.id, "compressed_server_key"), &compressed)
+                .await?;
+        }
+        self.write_metadata(&metadata).await?;
+        for name in KEY_FILES {
+            self.store.delete(name).await?;
+        }
+        if self.store.version("active").await?.is_none() {
+            self.store.write("active", metadata.id.as_bytes()).await?;
+        }
+        println!("[KmsService] imported legacy keys as keyset {}", metadata.id);
+        Ok(())
+    }
+}
+
+impl Keyset {
+    fn new(store: &Arc<dyn KeyStore>, metadata: KeysetMetadata) -> Self {
+        let entry = |name| keyset_entry(&metadata.id, name);
+        Self {
+            client_key: KeySlot::new(store.clone(), entry("client_key"), false),
+            server_key: KeySlot::new(store.clone(), entry("server_key"), true),
+            public_key: KeySlot::new(store.clone(), entry("public_key"), true),
+            crs: KeySlot::new(store.clone(), entry("crs"), true),
+            metadata,
+        }
+    }
+
+    pub async fn client_key(&self) -> Result<Arc<LoadedKey<SecretClientKey>>> {
+        self.client_key.get().await
+    }
+
+    pub async fn server_key(&self) -> Result<Arc<LoadedKey<ServerKey>>> {
+        self.server_key.get().await
+    }
+
+    pub async fn public_key(&self) -> Result<Arc<LoadedKey<CompactPublicKey>>> {
+        self.public_key.get().await
+    }
+
+    pub async fn crs(&self) -> Result<Arc<LoadedKey<CompactPkePublicParams>>> {
+        self.crs.get().await
+    }
+}
+
+// How a key entry is (de)serialized, bincode for the keys and tfhe's versioned
+// `safe_serialize` for the CRS whose curve points are not plain serde types
+trait KeyCodec: Sized {
+    fn encode(&self) -> Result<Vec<u8>>;
+    fn decode(bytes: &[u8]) -> Result<Self>;
+}
+
+macro_rules! bincode_codec {
+    ($($ty:ty),*) => {
+        $(impl KeyCodec for $ty {
+            fn encode(&self) -> Result<Vec<u8>> {
+                Ok(bincode::serialize(self)?)
+            }
+
+            fn decode(bytes: &[u8]) -> Result<Self> {
+                Ok(bincode::deserialize(bytes)?)
+            }
+        })*
+    };
+}
+
+bincode_codec!(ServerKey, CompactPublicKey);
+
+impl KeyCodec for SecretClientKey {
+    fn encode(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(&**self)?)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::new(bincode::deserialize(bytes)?))
     }
+}
 
-    pub async fn load_server(&self) -> Result<ServerKey> {
-        let server_key: ServerKey = load(&self.dir, "server_key").await?;
-        println!("[KmsService] server key loaded");
-        Ok(server_key)
+impl KeyCodec for CompactPkePublicParams {
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        safe_serialize(self, &mut bytes, CRS_SIZE_LIMIT)?;
+        Ok(bytes)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        safe_deserialize(bytes, CRS_SIZE_LIMIT).map_err(|e| anyhow!("invalid CRS: {}", e))
     }
 }
 
-// Helper functions to save and load keys
-async fn save<T: Serialize>(dir: &Path, name: &str, value: &T) -> Result<()> {
-    let bytes = bincode::serialize(value)?;
-    fs::write(dir.join(name), bytes).await?;
-    Ok(())
+impl<T> KeySlot<T>
+where
+    T: KeyCodec + Send + Sync + 'static,
+{
+    fn new(store: Arc<dyn KeyStore>, name: String, encode: bool) -> Self {
+        Self {
+            store,
+            name,
+            encode,
+            loaded: RwLock::new(None),
+        }
+    }
+
+    // Cached key, reloaded first if the file changed since it was read
+    async fn get(&self) -> Result<Arc<LoadedKey<T>>> {
+        let modified = self.version().await?;
+        if let Some(loaded) = self.loaded.read().await.as_ref()
+            && loaded.modified == modified
+        {
+            return Ok(loaded.clone());
+        }
+
+        let mut slot = self.loaded.write().await;
+        // Another request may have reloaded it while we waited for the lock
+        if let Some(loaded) = slot.as_ref()
+            && loaded.modified == modified
+        {
+            return Ok(loaded.clone());
+        }
+        let bytes = self
+            .store
+            .read(&self.name)
+            .await?
+            .ok_or_else(|| KeysetError::KeyMissing(self.name.clone()))?;
+        let encode = self.encode;
+        let loaded = tokio::task::spawn_blocking(move || -> Result<LoadedKey<T>> {
+            Ok(LoadedKey {
+                key: T::decode(&bytes)?,
+                encoded: if encode { BASE64.encode(&bytes).into() } else { Arc::from("") },
+                modified,
+            })
+        })
+        .await??;
+        let loaded = Arc::new(loaded);
+        *slot = Some(loaded.clone());
+        println!("[KmsService] {} loaded", self.name);
+        Ok(loaded)
+    }
+
+    async fn version(&self) -> Result<SystemTime> {
+        let version = self.store.version(&self.name).await?;
+        Ok(version.ok_or_else(|| KeysetError::KeyMissing(self.name.clone()))?)
+    }
+
+    // Write a new key to the store and put it straight into the cache, returns its serialized size
+    async fn store(&self, key: T) -> Result<usize> {
+        let mut slot = self.loaded.write().await;
+        let bytes = Zeroizing::new(key.encode()?);
+        self.store.write(&self.name, &bytes).await?;
+        let modified = self.version().await?;
+        let encoded = if self.encode { BASE64.encode(&bytes).into() } else { Arc::from("") };
+        *slot = Some(Arc::new(LoadedKey { key, encoded, modified }));
+        Ok(bytes.len())
+    }
+}
+
+// Keyset ids sort by creation time: `<unix seconds, hex>-<random suffix>`
+fn new_keyset_id() -> String {
+    let mut suffix = [0u8; 4];
+    OsRng.fill_bytes(&mut suffix);
+    format!("{:x}-{}", now(), hex::encode(suffix))
 }
 
-async fn load<T: DeserializeOwned>(dir: &Path, name: &str) -> Result<T> {
-    let bytes = fs::read(dir.join(name)).await?;
-    Ok(bincode::deserialize(&bytes)?)
-}
\ No newline at end of file
+fn keyset_entry(id: &str, name: &str) -> String {
+    format!("keysets/{}/{}", id, name)
+}
+
+// Whether a peer may push `name`: the active marker or a replicated entry of a keyset
+pub fn is_replicated_entry(name: &str) -> bool {
+    if name == "active" {
+        return true;
+    }
+    match name.split('/').collect::<Vec<_>>()[..] {
+        ["keysets", id, entry] => is_keyset_id(id) && REPLICATED.contains(&entry),
+        _ => false,
+    }
+}
+
+fn is_keyset_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn test_import_legacy_layout() {
+        let store: Arc<dyn KeyStore> = Arc::new(MemoryStore::new());
+        for name in KEY_FILES {
+            store.write(name, name.as_bytes()).await.unwrap();
+        }
+
+        let service = KmsService::new(store.clone(), None).await.unwrap();
+        let keysets = service.list().await;
+        assert_eq!(keysets.len(), 1);
+        let id = keysets[0].id.clone();
+        assert_eq!(service.active_id().await, Some(id.clone()));
+        for name in KEY_FILES {
+            assert!(store.read(name).await.unwrap().is_none());
+            let moved = store.read(&keyset_entry(&id, name)).await.unwrap().unwrap();
+            assert_eq!(&**moved, name.as_bytes());
+        }
+
+        // A restart finds the imported keyset again
+        let service = KmsService::new(store, None).await.unwrap();
+        assert_eq!(service.active_id().await, Some(id));
+        let missing = service.keyset(Some("missing")).await.err().unwrap();
+        assert!(matches!(missing.downcast_ref(), Some(KeysetError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_replicas_are_write_once() {
+        let service = KmsService::new(Arc::new(MemoryStore::new()), None).await.unwrap();
+        let id = "1-aa";
+        let entry = |name| keyset_entry(id, name);
+        service.apply_replica(&entry("public_key"), b"key").await.unwrap();
+        assert!(service.apply_replica(&entry("public_key"), b"other key").await.is_err());
+
+        let mut metadata = KeysetMetadata {
+            id: id.to_string(),
+            created_at: 1,
+            params: "tuniform-2m64".to_string(),
+            archived_at: None,
+            sizes: None,
+        };
+        let json = |metadata: &KeysetMetadata| serde_json::to_vec(metadata).unwrap();
+        service.apply_replica(&entry("metadata.json"), &json(&metadata)).await.unwrap();
+        // Nothing of a known keyset can be replaced, not even its missing entries
+        assert!(service.apply_replica(&entry("crs"), b"crs").await.is_err());
+        metadata.params = "compact-pke-2m64".to_string();
+        assert!(service.apply_replica(&entry("metadata.json"), &json(&metadata)).await.is_err());
+
+        // Archiving is the one change a peer may push
+        metadata.params = "tuniform-2m64".to_string();
+        metadata.archived_at = Some(2);
+        service.apply_replica(&entry("metadata.json"), &json(&metadata)).await.unwrap();
+        assert_eq!(service.keyset(Some(id)).await.unwrap().metadata.archived_at, Some(2));
+        metadata.archived_at = Some(3);
+        assert!(service.apply_replica(&entry("metadata.json"), &json(&metadata)).await.is_err());
+    }
+}
@@ -1,17 +1,47 @@
 use std::net::SocketAddr;
 
+mod acl;
+mod audit;
+mod auth;
+mod cluster;
+mod config;
+mod coprocessor;
+mod decryption;
+mod eip712;
+mod error;
 mod handlers;
+mod jobs;
 mod kms;
+mod params;
+mod rbac;
 mod routes;
+mod sealing;
+mod shamir;
+mod signer;
 mod state;
+mod store;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let keys_dir = std::env::var("KEYS_DIR").unwrap_or_else(|_| "./keys".to_string());
-    let app = routes::create_router(state::KmsState::new(keys_dir.into()).await?);
-    let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let addr = SocketAddr::from(([0, 0, 0, 0], port.parse().unwrap()));
+    let config = config::KmsConfig::from_env()?;
+    if std::env::args().nth(1).as_deref() == Some("migrate-keys") {
+        return migrate_keys(&config).await;
+    }
+    let app = routes::create_router(state::KmsState::new(&config).await?);
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
     println!("Starting KMS service on address {}", addr);
-    axum::serve(tokio::net::TcpListener::bind(addr).await?, app).await?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+    Ok(())
+}
+
+// `KMS migrate-keys`: seal the plaintext secret keys left in KEYS_DIR
+async fn migrate_keys(config: &config::KmsConfig) -> anyhow::Result<()> {
+    let Some(sealer) = sealing::Sealer::from_env(config.kek_file.as_deref()).await? else {
+        anyhow::bail!("set KMS_PASSPHRASE or KMS_KEK_FILE to migrate keys");
+    };
+    let fs_store = store::FsStore::new(config.keys_dir.clone());
+    let sealed = store::SealedStore::new(std::sync::Arc::new(fs_store), sealer).migrate().await?;
+    println!("[KMS] sealed {} key file(s) in {:?}", sealed, config.keys_dir);
     Ok(())
 }
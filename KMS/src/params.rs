@@ -0,0 +1,155 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use tfhe::shortint::parameters::compact_public_key_only::p_fail_2_minus_64::ks_pbs::PARAM_PKE_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64;
+use tfhe::shortint::parameters::key_switching::p_fail_2_minus_64::ks_pbs::PARAM_KEYSWITCH_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64;
+use tfhe::shortint::parameters::PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64;
+use tfhe::{Config, ConfigBuilder};
+use thiserror::Error;
+use crate::error::error_response;
+
+// ParamPreset names a TFHE configuration a keyset can be generated with
+// The name is stored in the keyset metadata, clients and the coprocessor compare
+// it (or the fields below, from GET /params) against the config they were built for
+#[derive(Debug, Serialize)]
+pub struct ParamPreset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub security_bits: u32,
+    // Probability of a wrong bootstrapping result
+    pub failure_probability: &'static str,
+    // Noise distribution of the LWE secret key encryptions
+    pub noise_distribution: &'static str,
+    pub message_bits: u32,
+    pub carry_bits: u32,
+    // Dedicated parameters of the compact public key, None when it uses the block parameters
+    pub compact_pke: Option<&'static str>,
+    // Whether new keysets can use it, which needs a CRS and so bounded (TUniform) noise
+    pub generate: bool,
+    #[serde(skip)]
+    build: fn() -> Config,
+}
+
+impl ParamPreset {
+    pub fn config(&self) -> Config {
+        (self.build)()
+    }
+}
+
+pub static PRESETS: [ParamPreset; 3] = [
+    ParamPreset {
+        name: "default",
+        description: "tfhe-rs default configuration, Gaussian noise in tfhe 0.8. Keys from before presets used it, it can't get a CRS so new keysets can't",
+        security_bits: 128,
+        failure_probability: "2^-64",
+        noise_distribution: "gaussian",
+        message_bits: 2,
+        carry_bits: 2,
+        compact_pke: None,
+        generate: false,
+        build: || ConfigBuilder::default().build(),
+    },
+    ParamPreset {
+        name: "tuniform-2m64",
+        description: "2 bit message, 2 bit carry blocks with TUniform noise",
+        security_bits: 128,
+        failure_probability: "2^-64",
+        noise_distribution: "tuniform",
+        message_bits: 2,
+        carry_bits: 2,
+        compact_pke: None,
+        generate: true,
+        build: || ConfigBuilder::with_custom_parameters(PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64).build(),
+    },
+    ParamPreset {
+        name: "compact-pke-2m64",
+        description: "tuniform-2m64 blocks with a dedicated compact public key, expanding client inputs needs the server key",
+        security_bits: 128,
+        failure_probability: "2^-64",
+        noise_distribution: "tuniform",
+        message_bits: 2,
+        carry_bits: 2,
+        compact_pke: Some("PARAM_PKE_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64"),
+        generate: true,
+        build: || {
+            ConfigBuilder::with_custom_parameters(PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64)
+                .use_dedicated_compact_public_key_parameters((
+                    PARAM_PKE_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64,
+                    PARAM_KEYSWITCH_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64,
+                ))
+                .build()
+        },
+    },
+];
+
+#[derive(Debug, Error)]
+pub enum PresetError {
+    #[error("unknown parameter preset {0:?}, expected one of: {known}", known = known(|_| true))]
+    Unknown(String),
+    // Every keyset gets a CRS, and tfhe only builds one for bounded noise
+    #[error("parameter preset {0:?} has no CRS support, generate with one of: {known}", known = known(|p| p.generate))]
+    NotGeneratable(String),
+}
+
+impl PresetError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PresetError::Unknown(_) => "unknown_params",
+            PresetError::NotGeneratable(_) => "unsupported_params",
+        }
+    }
+}
+
+impl IntoResponse for PresetError {
+    fn into_response(self) -> Response {
+        error_response(StatusCode::BAD_REQUEST, self.code(), &self.to_string())
+    }
+}
+
+pub fn preset(name: &str) -> Result<&'static ParamPreset, PresetError> {
+    PRESETS
+        .iter()
+        .find(|preset| preset.name == name)
+        .ok_or_else(|| PresetError::Unknown(name.to_string()))
+}
+
+// Preset a new keyset can be generated with
+pub fn generation_preset(name: &str) -> Result<&'static ParamPreset, PresetError> {
+    let preset = preset(name)?;
+    if !preset.generate {
+        return Err(PresetError::NotGeneratable(name.to_string()));
+    }
+    Ok(preset)
+}
+
+fn known(filter: fn(&ParamPreset) -> bool) -> String {
+    PRESETS
+        .iter()
+        .filter(|preset| filter(preset))
+        .map(|preset| preset.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_lookup() {
+        for known in &PRESETS {
+            assert_eq!(preset(known.name).unwrap().name, known.name);
+            // CRS generation rejects anything but TUniform noise
+            assert_eq!(known.generate, known.noise_distribution == "tuniform");
+        }
+        let error = preset("fast-and-loose").unwrap_err().to_string();
+        assert!(error.contains("fast-and-loose"));
+        assert!(error.contains("tuniform-2m64"));
+
+        assert!(preset("default").is_ok());
+        let error = generation_preset("default").unwrap_err();
+        assert_eq!(error.code(), "unsupported_params");
+        assert!(!error.to_string().contains("default,"));
+        assert!(generation_preset("tuniform-2m64").is_ok());
+    }
+}
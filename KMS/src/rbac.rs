@@ -0,0 +1,166 @@
+use std::net::SocketAddr;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use crate::audit::AuditEvent;
+use crate::auth::{bearer_token, constant_time_eq, AuthError};
+use crate::config::KmsConfig;
+use crate::state::KmsState;
+
+// Role a caller acts as, picked from its bearer token
+// Requests without a token are `Public`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    // Public key, CRS, parameters, key info and signed user decryption
+    Public,
+    // Server key download
+    Coprocessor,
+    // Public decryption, what the coprocessor's decryption oracle calls
+    Decrypt,
+    // Replication and key share requests between KMS nodes
+    Peer,
+    // Key generation, rotation and activation
+    Admin,
+}
+
+impl Role {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "public" => Some(Role::Public),
+            "coprocessor" => Some(Role::Coprocessor),
+            "decrypt" => Some(Role::Decrypt),
+            "peer" => Some(Role::Peer),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Public => "public",
+            Role::Coprocessor => "coprocessor",
+            Role::Decrypt => "decrypt",
+            Role::Peer => "peer",
+            Role::Admin => "admin",
+        }
+    }
+
+    // Admins may also download the server key, but not decrypt, peers only talk to peers
+    fn grants(self, required: Role) -> bool {
+        match (self, required) {
+            (_, Role::Public) => true,
+            (Role::Admin, Role::Coprocessor) => true,
+            (role, required) => role == required,
+        }
+    }
+}
+
+// AccessPolicy maps bearer tokens to roles
+pub struct AccessPolicy {
+    tokens: Vec<(String, Role)>,
+}
+
+impl AccessPolicy {
+    // KMS_API_TOKENS (`role:token,...`), the single-role tokens ADMIN_API_TOKEN and
+    // DECRYPT_API_TOKEN, plus one peer token per peer from KMS_PEER_TOKENS
+    pub fn from_config(config: &KmsConfig) -> Self {
+        let policy = Self::new(config.api_tokens.clone());
+        for role in [Role::Admin, Role::Coprocessor, Role::Decrypt, Role::Peer] {
+            let count = policy.tokens.iter().filter(|(_, r)| *r == role).count();
+            if count == 0 {
+                println!("[Rbac] no {} token configured, {} routes reject every call", role.name(), role.name());
+            } else {
+                println!("[Rbac] {} {} token(s)", count, role.name());
+            }
+        }
+        policy
+    }
+
+    pub fn new(tokens: Vec<(String, Role)>) -> Self {
+        Self { tokens }
+    }
+
+    // Role of the caller, an unknown token is an error rather than public access
+    pub fn role_of(&self, headers: &HeaderMap) -> Result<Role, AuthError> {
+        let Some(given) = bearer_token(headers) else {
+            return Ok(Role::Public);
+        };
+        // Compare against every token so timing doesn't tell which one was close
+        let mut role = None;
+        for (token, token_role) in &self.tokens {
+            if constant_time_eq(token.as_bytes(), given.as_bytes()) {
+                role = Some(*token_role);
+            }
+        }
+        role.ok_or(AuthError::InvalidToken)
+    }
+}
+
+// Middleware guarding a group of routes with `required`, every denial is logged and audited
+pub async fn authorize(
+    State((state, required)): State<(KmsState, Role)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let result = state.access.role_of(request.headers()).and_then(|role| match role {
+        role if role.grants(required) => Ok(()),
+        Role::Public => Err(AuthError::InvalidToken),
+        role => Err(AuthError::Forbidden(role.name())),
+    });
+    let Err(e) = result else {
+        return next.run(request).await;
+    };
+
+    let caller = match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => addr.to_string(),
+        None => "unknown".to_string(),
+    };
+    let route = format!("{} {}", request.method(), request.uri().path());
+    println!("[Rbac] denied {} from {} (requires {}): {}", route, caller, required.name(), e);
+    let event = AuditEvent {
+        action: "access_denied",
+        caller,
+        handles: vec![route],
+        outcome: if matches!(e, AuthError::InvalidToken) { "unauthorized" } else { "forbidden" },
+    };
+    state.audit.record(event).await;
+    e.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header::AUTHORIZATION, HeaderValue};
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", token)).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_roles() {
+        let policy = AccessPolicy::new(vec![
+            ("a".to_string(), Role::Admin),
+            ("c".to_string(), Role::Coprocessor),
+            ("d".to_string(), Role::Decrypt),
+            ("p".to_string(), Role::Peer),
+        ]);
+        assert_eq!(policy.role_of(&HeaderMap::new()).unwrap(), Role::Public);
+        assert_eq!(policy.role_of(&bearer("a")).unwrap(), Role::Admin);
+        assert_eq!(policy.role_of(&bearer("p")).unwrap(), Role::Peer);
+        assert!(matches!(policy.role_of(&bearer("x")), Err(AuthError::InvalidToken)));
+
+        assert!(Role::Admin.grants(Role::Coprocessor));
+        assert!(Role::Peer.grants(Role::Public));
+        assert!(!Role::Coprocessor.grants(Role::Admin));
+        assert!(!Role::Admin.grants(Role::Peer));
+        assert!(!Role::Admin.grants(Role::Decrypt));
+        assert!(!Role::Decrypt.grants(Role::Coprocessor));
+        assert!(!Role::Coprocessor.grants(Role::Decrypt));
+        assert!(!Role::Public.grants(Role::Coprocessor));
+    }
+}
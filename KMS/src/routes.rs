@@ -1,19 +1,125 @@
-use crate::handlers::{health::health, keys};
+use crate::error;
+use crate::handlers::{decrypt, download, health::health, keys, params, peer, signer, user_decrypt};
+use crate::rbac::{self, Role};
 use crate::state::KmsState;
-use axum::{routing::{get, post}, Router};
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{get, post, put},
+    Router,
+};
 
+// Routes are grouped by the role they require, see rbac::Role
 pub fn create_router(state: KmsState) -> Router {
-    Router::new()
+    let public = Router::new()
         .route("/", get(health))
         .route("/health", get(health))
-        .route("/keys/generate", post(keys::generate))
+        .route("/keys", get(keys::list))
+        .route("/jobs/{id}", get(keys::job))
+        .route("/keys/active", get(keys::active))
+        .route("/keys/info", get(keys::info))
         .route("/keys/public", get(keys::public_key))
+        .route("/keys/public/raw", get(download::public_key))
+        .route("/keys/crs", get(keys::crs))
+        .route("/keys/crs/raw", get(download::crs))
+        .route("/keys/{id}/info", get(keys::keyset_info))
+        .route("/keys/{id}/public", get(keys::keyset_public_key))
+        .route("/keys/{id}/public/raw", get(download::keyset_public_key))
+        .route("/keys/{id}/crs", get(keys::keyset_crs))
+        .route("/keys/{id}/crs/raw", get(download::keyset_crs))
+        .route("/params", get(params::list))
+        .route("/params/{name}", get(params::get))
+        .route("/signer", get(signer::signer))
+        // Authorized by the user's EIP-712 signature
+        .route("/decrypt/user", post(user_decrypt::user_decrypt));
+
+    let coprocessor = Router::new()
         .route("/keys/server", get(keys::server_key))
+        // Binary downloads, streamed with ETag and Range support
+        .route("/keys/server/raw", get(download::server_key))
+        .route("/keys/server/compressed/raw", get(download::compressed_server_key))
+        .route("/keys/{id}/server", get(keys::keyset_server_key))
+        .route("/keys/{id}/server/raw", get(download::keyset_server_key))
+        .route("/keys/{id}/server/compressed/raw", get(download::keyset_compressed_server_key))
+        .route_layer(middleware::from_fn_with_state((state.clone(), Role::Coprocessor), rbac::authorize));
+
+    let decryption = Router::new()
+        .route("/decrypt", post(decrypt::decrypt))
+        .route_layer(middleware::from_fn_with_state((state.clone(), Role::Decrypt), rbac::authorize));
+
+    let admin = Router::new()
+        .route("/keys/generate", post(keys::generate))
+        .route("/keys/rotate", post(keys::rotate))
+        .route("/keys/{id}/activate", post(keys::activate))
+        .route_layer(middleware::from_fn_with_state((state.clone(), Role::Admin), rbac::authorize));
+
+    let peer = Router::new()
+        // Server keys are far beyond the default body limit
+        .route("/peer/entries/{*name}", put(peer::put_entry).layer(DefaultBodyLimit::disable()))
+        .route("/peer/keysets/{id}/share", post(peer::share).put(peer::put_share))
+        .route_layer(middleware::from_fn_with_state((state.clone(), Role::Peer), rbac::authorize));
+
+    Router::new()
+        .merge(public)
+        .merge(coprocessor)
+        .merge(decryption)
+        .merge(admin)
+        .merge(peer)
         .with_state(state)
+        // Outermost, so rejections from the role layers carry a request id too
+        .layer(middleware::from_fn(error::assign_request_id))
 }
 
-// --- IGNORE ---
-// POST method to generate and store keys
-// GET method to retrieve public key
-// GET method to retrieve server key
-// POST method to decrypt payload
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use crate::config::KmsConfig;
+
+    #[tokio::test]
+    async fn test_roles_are_enforced_per_route() {
+        let dir = std::env::temp_dir().join(format!("kms-routes-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let mut config = KmsConfig::for_tests(&dir);
+        config.api_tokens = vec![
+            ("admin-token".to_string(), Role::Admin),
+            ("coprocessor-token".to_string(), Role::Coprocessor),
+            ("decrypt-token".to_string(), Role::Decrypt),
+        ];
+        let app = create_router(KmsState::new(&config).await.unwrap());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).into_future());
+
+        let client = reqwest::Client::new();
+        let call = |method: reqwest::Method, path: &str, token: Option<&str>| {
+            let mut request = client.request(method, format!("{}{}", url, path));
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            request.send()
+        };
+        let cases = [
+            (reqwest::Method::POST, "/keys/generate", None, 401),
+            (reqwest::Method::POST, "/keys/generate", Some("wrong-token"), 401),
+            (reqwest::Method::POST, "/keys/generate", Some("coprocessor-token"), 403),
+            (reqwest::Method::POST, "/keys/rotate", Some("decrypt-token"), 403),
+            (reqwest::Method::GET, "/keys/server/raw", Some("decrypt-token"), 403),
+            (reqwest::Method::POST, "/decrypt", Some("coprocessor-token"), 403),
+            (reqwest::Method::POST, "/decrypt", Some("admin-token"), 403),
+            (reqwest::Method::POST, "/peer/keysets/a/share", Some("admin-token"), 403),
+            // Let through, no keyset exists yet
+            (reqwest::Method::GET, "/keys/server/raw", Some("coprocessor-token"), 404),
+            (reqwest::Method::GET, "/keys/server/raw", Some("admin-token"), 404),
+        ];
+        for (method, path, token, status) in cases {
+            let response = call(method.clone(), path, token).await.unwrap();
+            assert_eq!(response.status().as_u16(), status, "{} {} with {:?}", method, path, token);
+            if status == 403 {
+                let body: serde_json::Value = response.json().await.unwrap();
+                assert_eq!(body["error"], "forbidden");
+            }
+        }
+        tokio::fs::remove_dir_all(&dir).await.ok();
+    }
+}
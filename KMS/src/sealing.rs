@@ -0,0 +1,115 @@
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::path::Path;
+use tokio::fs;
+use zeroize::Zeroizing;
+
+// Sealed file layout: MAGIC | salt (16) | nonce (12) | ciphertext + tag
+const MAGIC: &[u8; 8] = b"KMSSEAL1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+// Sealer encrypts secret key files at rest
+// The AEAD key is derived per file with Argon2id from the operator secret (KMS_PASSPHRASE
+// or the contents of KMS_KEK_FILE) and a random salt stored in the file header
+pub struct Sealer {
+    secret: Zeroizing<Vec<u8>>,
+}
+
+impl Sealer {
+    pub fn new(secret: Zeroizing<Vec<u8>>) -> Result<Self> {
+        if secret.is_empty() {
+            bail!("key encryption secret is empty");
+        }
+        Ok(Self { secret })
+    }
+
+    // KMS_PASSPHRASE wins over KMS_KEK_FILE, None when neither is set
+    pub async fn from_env(kek_file: Option<&Path>) -> Result<Option<Self>> {
+        if let Ok(passphrase) = std::env::var("KMS_PASSPHRASE") {
+            return Ok(Some(Self::new(Zeroizing::new(passphrase.into_bytes()))?));
+        }
+        match kek_file {
+            Some(path) => {
+                let secret = fs::read(path)
+                    .await
+                    .with_context(|| format!("failed to read KMS_KEK_FILE {:?}", path))?;
+                Ok(Some(Self::new(Zeroizing::new(secret))?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // `label` is bound to the ciphertext (e.g. the file name), a sealed client key
+    // can't be swapped in for another file
+    pub fn seal(&self, plaintext: &[u8], label: &str) -> Result<Vec<u8>> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let cipher = self.cipher(&salt)?;
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: label.as_bytes() })
+            .map_err(|_| anyhow!("failed to seal {}", label))?;
+
+        let mut sealed = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.extend_from_slice(&salt);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn open(&self, sealed: &[u8], label: &str) -> Result<Zeroizing<Vec<u8>>> {
+        if !is_sealed(sealed) || sealed.len() < HEADER_LEN {
+            bail!("{} is not a sealed key file", label);
+        }
+        let salt = &sealed[MAGIC.len()..MAGIC.len() + SALT_LEN];
+        let nonce = Nonce::from_slice(&sealed[MAGIC.len() + SALT_LEN..HEADER_LEN]);
+        let cipher = self.cipher(salt)?;
+        let plaintext = cipher
+            .decrypt(nonce, Payload { msg: &sealed[HEADER_LEN..], aad: label.as_bytes() })
+            .map_err(|_| anyhow!("failed to unseal {}: wrong passphrase or corrupted file", label))?;
+        Ok(Zeroizing::new(plaintext))
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::default()
+            .hash_password_into(&self.secret, salt, key.as_mut())
+            .map_err(|e| anyhow!("key derivation failed: {}", e))?;
+        Ok(ChaCha20Poly1305::new(Key::from_slice(key.as_ref())))
+    }
+}
+
+pub fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sealer(secret: &str) -> Sealer {
+        Sealer::new(Zeroizing::new(secret.as_bytes().to_vec())).unwrap()
+    }
+
+    #[test]
+    fn test_seal_roundtrip() {
+        let sealer = sealer("correct horse battery staple");
+        let sealed = sealer.seal(b"client key bytes", "client_key").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.windows(6).any(|w| w == b"client"));
+        assert_eq!(&*sealer.open(&sealed, "client_key").unwrap(), b"client key bytes");
+
+        // Wrong secret, wrong label and tampering all fail
+        assert!(self::sealer("wrong").open(&sealed, "client_key").is_err());
+        assert!(sealer.open(&sealed, "signer_key").is_err());
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(sealer.open(&tampered, "client_key").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::{bail, Result};
+use crypto_box::aead::rand_core::RngCore;
+use crypto_box::aead::OsRng;
+use zeroize::Zeroizing;
+
+// Shamir secret sharing over GF(2^8), applied byte by byte
+// A share is `x || f(x)` where f is a random polynomial of degree threshold - 1
+// per secret byte with the secret byte as constant term, any `threshold` shares
+// recover the secret and fewer reveal nothing about it.
+pub struct Share {
+    pub index: u8,
+    pub data: Zeroizing<Vec<u8>>,
+}
+
+impl Share {
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut bytes = Zeroizing::new(Vec::with_capacity(1 + self.data.len()));
+        bytes.push(self.index);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.split_first() {
+            Some((&index, data)) if index != 0 => Ok(Self {
+                index,
+                data: Zeroizing::new(data.to_vec()),
+            }),
+            _ => bail!("malformed key share"),
+        }
+    }
+}
+
+// Split `secret` into shares for indices 1..=n
+pub fn split(secret: &[u8], n: u8, threshold: u8) -> Result<Vec<Share>> {
+    if threshold == 0 || threshold > n {
+        bail!("threshold must be between 1 and {}, got {}", n, threshold);
+    }
+    let mut shares: Vec<Share> = (1..=n)
+        .map(|index| Share {
+            index,
+            data: Zeroizing::new(Vec::with_capacity(secret.len())),
+        })
+        .collect();
+    let mut coefficients = Zeroizing::new(vec![0u8; threshold as usize]);
+    for &byte in secret {
+        coefficients[0] = byte;
+        OsRng.fill_bytes(&mut coefficients[1..]);
+        for share in &mut shares {
+            // Horner evaluation of the polynomial at x = index
+            let y = coefficients.iter().rev().fold(0, |acc, &c| mul(acc, share.index) ^ c);
+            share.data.push(y);
+        }
+    }
+    Ok(shares)
+}
+
+// Recover the secret from shares with distinct indices, all of them are used
+// Passing fewer than `threshold` shares yields garbage, not an error
+pub fn combine(shares: &[Share]) -> Result<Zeroizing<Vec<u8>>> {
+    let Some(first) = shares.first() else {
+        bail!("no shares to combine");
+    };
+    let len = first.data.len();
+    for (i, share) in shares.iter().enumerate() {
+        if share.index == 0 || share.data.len() != len {
+            bail!("malformed key share {}", share.index);
+        }
+        if shares[..i].iter().any(|other| other.index == share.index) {
+            bail!("duplicate key share {}", share.index);
+        }
+    }
+
+    // Lagrange basis polynomials evaluated at x = 0 (subtraction is xor in GF(2^8))
+    let basis: Vec<u8> = shares
+        .iter()
+        .map(|share| {
+            shares
+                .iter()
+                .filter(|other| other.index != share.index)
+                .fold(1, |acc, other| {
+                    mul(acc, mul(other.index, inv(other.index ^ share.index)))
+                })
+        })
+        .collect();
+
+    let mut secret = Zeroizing::new(vec![0u8; len]);
+    for (share, &weight) in shares.iter().zip(&basis) {
+        for (out, &y) in secret.iter_mut().zip(share.data.iter()) {
+            *out ^= mul(y, weight);
+        }
+    }
+    Ok(secret)
+}
+
+// Multiplication modulo the AES polynomial x^8 + x^4 + x^3 + x + 1
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 = a^-1 for a != 0
+fn inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp != 0 {
+        if exp & 1 != 0 {
+            result = mul(result, base);
+        }
+        base = mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_any_threshold_subset_recovers() {
+        let secret = b"serialized client key".to_vec();
+        let shares = split(&secret, 5, 3).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        for subset in [[0, 1, 2], [0, 2, 4], [4, 3, 1], [1, 2, 3]] {
+            let picked: Vec<Share> = subset
+                .iter()
+                .map(|&i| Share::from_bytes(&shares[i].to_bytes()).unwrap())
+                .collect();
+            assert_eq!(&*combine(&picked).unwrap(), &secret);
+        }
+
+        // Below the threshold the result has nothing to do with the secret
+        assert_ne!(&*combine(&shares[..2]).unwrap(), &secret);
+        assert!(split(&secret, 3, 4).is_err());
+    }
+
+    #[test]
+    fn test_field_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, inv(a)), 1);
+        }
+    }
+}
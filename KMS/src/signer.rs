@@ -0,0 +1,125 @@
+use alloy::primitives::{Address, Bytes, B256};
+use alloy::signers::{local::PrivateKeySigner, SignerSync};
+use alloy::sol_types::SolStruct;
+use anyhow::{Context, Result};
+use zeroize::Zeroizing;
+use crate::eip712::{decryption_domain, PublicDecryptVerification};
+use crate::store::KeyStore;
+
+// KmsSigner holds the secp256k1 key registered as a KMS signer in KMSVerifier
+// It signs public decryption results so they can be checked on chain
+pub struct KmsSigner {
+    signer: PrivateKeySigner,
+    gateway_chain_id: u64,
+    verifying_contract: Address,
+}
+
+impl KmsSigner {
+    // Use KMS_SIGNER_KEY when given, otherwise the `signer_key` entry of the key store,
+    // generating one on first start
+    pub async fn load(
+        store: &dyn KeyStore,
+        configured: Option<&str>,
+        gateway_chain_id: u64,
+        verifying_contract: Address,
+    ) -> Result<Self> {
+        let stored = match configured {
+            Some(_) => None,
+            None => store.read("signer_key").await?,
+        };
+        let signer: PrivateKeySigner = match (configured, stored) {
+            (Some(key), _) => key.parse().context("KMS_SIGNER_KEY is not a valid private key")?,
+            (None, Some(key)) => {
+                std::str::from_utf8(&key)
+                    .context("stored signer_key is invalid")?
+                    .trim()
+                    .parse()
+                    .context("stored signer_key is invalid")?
+            }
+            (None, None) => {
+                let signer = PrivateKeySigner::random();
+                let key = Zeroizing::new(hex::encode(signer.to_bytes()));
+                store.write("signer_key", key.as_bytes()).await?;
+                println!("[KmsSigner] generated new signing key in {}", store.describe());
+                signer
+            }
+        };
+        println!("[KmsSigner] signer address: {}", signer.address());
+        Ok(Self {
+            signer,
+            gateway_chain_id,
+            verifying_contract,
+        })
+    }
+
+    pub fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    pub fn gateway_chain_id(&self) -> u64 {
+        self.gateway_chain_id
+    }
+
+    pub fn verifying_contract(&self) -> Address {
+        self.verifying_contract
+    }
+
+    // Sign plaintexts of `handles` as KMSVerifier expects them
+    // The decrypted result is the concatenation of the 32 byte values (abi.encode of static types)
+    pub fn sign_public_decryption(&self, handles: &[B256], plaintexts: &[[u8; 32]]) -> Result<Bytes> {
+        let message = PublicDecryptVerification {
+            ctHandles: handles.to_vec(),
+            decryptedResult: plaintexts.concat().into(),
+            extraData: Bytes::new(),
+        };
+        let domain = decryption_domain(self.gateway_chain_id, self.verifying_contract);
+        let signature = self.signer.sign_hash_sync(&message.eip712_signing_hash(&domain))?;
+        Ok(Bytes::copy_from_slice(&signature.as_bytes()))
+    }
+}
+
+// KMSVerifier decryption proof: number of signatures, the signatures, then extra data
+pub fn decryption_proof(signatures: &[Bytes]) -> Bytes {
+    let mut proof = vec![signatures.len() as u8];
+    for signature in signatures {
+        proof.extend_from_slice(signature);
+    }
+    proof.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::Signature;
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn test_signature_recovers_to_signer() {
+        let store = MemoryStore::new();
+        let verifying_contract = Address::repeat_byte(7);
+        let signer = KmsSigner::load(&store, None, 31337, verifying_contract).await.unwrap();
+        // The generated key is persisted and reused
+        let reloaded = KmsSigner::load(&store, None, 31337, verifying_contract).await.unwrap();
+        assert_eq!(signer.address(), reloaded.address());
+
+        let handles = [B256::repeat_byte(1), B256::repeat_byte(2)];
+        let plaintexts = [[0u8; 32], [1u8; 32]];
+        let signature = signer.sign_public_decryption(&handles, &plaintexts).unwrap();
+
+        let message = PublicDecryptVerification {
+            ctHandles: handles.to_vec(),
+            decryptedResult: plaintexts.concat().into(),
+            extraData: Bytes::new(),
+        };
+        let hash = message.eip712_signing_hash(&decryption_domain(31337, verifying_contract));
+        let recovered = Signature::try_from(signature.as_ref())
+            .unwrap()
+            .recover_address_from_prehash(&hash)
+            .unwrap();
+        assert_eq!(recovered, signer.address());
+
+        let proof = decryption_proof(std::slice::from_ref(&signature));
+        assert_eq!(proof[0], 1);
+        assert_eq!(&proof[1..], signature.as_ref());
+    }
+}
@@ -1,15 +1,63 @@
-use std::path::PathBuf;
+use std::sync::Arc;
 use anyhow::Result;
+use crate::acl::AclClient;
+use crate::audit::AuditLog;
+use crate::auth::Authorizer;
+use crate::cluster::Cluster;
+use crate::config::KmsConfig;
+use crate::coprocessor::CoprocessorClient;
+use crate::jobs::Jobs;
 use crate::kms::KmsService;
+use crate::params::{self, ParamPreset};
+use crate::rbac::AccessPolicy;
+use crate::signer::KmsSigner;
+use crate::store;
 
 #[derive(Clone)]
 pub struct KmsState {
     pub kms_service: KmsService,
+    pub jobs: Jobs,
+    pub access: Arc<AccessPolicy>,
+    pub default_params: &'static ParamPreset,
+    pub audit: Arc<AuditLog>,
+    pub authorizer: Arc<Authorizer>,
+    pub acl: Option<AclClient>,
+    pub coprocessor: CoprocessorClient,
+    pub signer: Arc<KmsSigner>,
 }
 
 impl KmsState {
-    pub async fn new(key_dir: PathBuf) -> Result<Self> {
-        println!("[KmsState] initializing with key_dir: {:?}", key_dir);
-        Ok(Self { kms_service: KmsService::new(key_dir).await? })
+    pub async fn new(config: &KmsConfig) -> Result<Self> {
+        println!("[KmsState] initializing with key_dir: {:?}", config.keys_dir);
+        let acl = match config.acl_address {
+            Some(address) => Some(AclClient::new(&config.rpc_url, address)?),
+            None => {
+                println!("[KmsState] ACL_ADDRESS not set, decryption requests will be rejected");
+                None
+            }
+        };
+        let default_params = params::generation_preset(&config.tfhe_params)?;
+        println!("[KmsState] new keysets use the {} parameter preset", default_params.name);
+        let store = store::open(config).await?;
+        let cluster = Cluster::from_config(config)?.map(Arc::new);
+        let kms_service = KmsService::new(store.clone(), cluster).await?;
+        let signer = KmsSigner::load(
+            store.as_ref(),
+            config.signer_key.as_deref(),
+            config.gateway_chain_id,
+            config.verifying_contract,
+        )
+        .await?;
+        Ok(Self {
+            kms_service,
+            jobs: Jobs::new(),
+            access: Arc::new(AccessPolicy::from_config(config)),
+            default_params,
+            audit: Arc::new(AuditLog::open(&config.audit_log).await?),
+            authorizer: Arc::new(Authorizer::new(config.chain_id, config.auth_max_ttl)),
+            acl,
+            coprocessor: CoprocessorClient::new(config.coprocessor_url.clone(), config.coprocessor_token.clone()),
+            signer: Arc::new(signer),
+        })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tokio::fs;
+use zeroize::Zeroizing;
+use super::{EntryReader, KeyStore};
+
+// FsStore keeps every entry as a file below `dir`
+pub struct FsStore {
+    dir: PathBuf,
+}
+
+impl FsStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+}
+
+#[async_trait]
+impl KeyStore for FsStore {
+    async fn read(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        match fs::read(self.path(name)).await {
+            Ok(bytes) => Ok(Some(Zeroizing::new(bytes))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Write next to the file and rename it over, keeping the permissions of the
+    // file it replaces (archived keys stay read-only)
+    async fn write(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        let path = self.path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes).await?;
+        if let Ok(meta) = fs::metadata(&path).await {
+            fs::set_permissions(&tmp, meta.permissions()).await?;
+        }
+        fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        match fs::remove_file(self.path(name)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn version(&self, name: &str) -> Result<Option<SystemTime>> {
+        match fs::metadata(self.path(name)).await {
+            Ok(meta) => Ok(Some(meta.modified()?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut entries = match fs::read_dir(self.path(prefix)).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    async fn open(&self, name: &str) -> Result<Option<EntryReader>> {
+        let file = match fs::File::open(self.path(name)).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let len = file.metadata().await?.len();
+        Ok(Some(EntryReader {
+            len,
+            reader: Box::new(file),
+        }))
+    }
+
+    async fn protect(&self, name: &str) -> Result<()> {
+        let path = self.path(name);
+        let mut permissions = fs::metadata(&path).await?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).await?;
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!("file store at {:?}", self.dir)
+    }
+}
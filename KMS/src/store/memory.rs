@@ -0,0 +1,72 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+use tokio::sync::RwLock;
+use zeroize::Zeroizing;
+use super::KeyStore;
+
+// MemoryStore keeps entries in a map, for tests and throwaway deployments
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: RwLock<BTreeMap<String, Entry>>,
+}
+
+struct Entry {
+    bytes: Zeroizing<Vec<u8>>,
+    version: SystemTime,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl KeyStore for MemoryStore {
+    async fn read(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        let entries = self.entries.read().await;
+        Ok(entries.get(name).map(|entry| entry.bytes.clone()))
+    }
+
+    async fn write(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        let mut entries = self.entries.write().await;
+        // Keep versions strictly increasing even when the clock doesn't move
+        let mut version = SystemTime::now();
+        if let Some(previous) = entries.get(name)
+            && version <= previous.version
+        {
+            version = previous.version + std::time::Duration::from_nanos(1);
+        }
+        let bytes = Zeroizing::new(bytes.to_vec());
+        entries.insert(name.to_string(), Entry { bytes, version });
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        self.entries.write().await.remove(name);
+        Ok(())
+    }
+
+    async fn version(&self, name: &str) -> Result<Option<SystemTime>> {
+        Ok(self.entries.read().await.get(name).map(|entry| entry.version))
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let prefix = format!("{}/", prefix.trim_end_matches('/'));
+        let entries = self.entries.read().await;
+        let mut names: Vec<String> = entries
+            .keys()
+            .filter_map(|name| name.strip_prefix(&prefix))
+            .filter_map(|rest| rest.split('/').next())
+            .map(str::to_string)
+            .collect();
+        names.dedup();
+        Ok(names)
+    }
+
+    fn describe(&self) -> String {
+        "in-memory store".to_string()
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::io::{AsyncRead, AsyncSeek};
+use zeroize::Zeroizing;
+use crate::config::KmsConfig;
+use crate::sealing::Sealer;
+
+mod fs;
+mod memory;
+mod sealed;
+
+pub use fs::FsStore;
+pub use memory::MemoryStore;
+pub use sealed::SealedStore;
+
+// KeyStore is where the KMS keeps its key material and keyset bookkeeping
+// Entries are addressed by `/` separated names relative to the store root,
+// e.g. `keysets/<id>/client_key` or `active`
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+    // Contents of `name`, None when it does not exist
+    async fn read(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>>;
+
+    // Replace `name` as a whole, readers never see a partial write
+    async fn write(&self, name: &str, bytes: &[u8]) -> Result<()>;
+
+    async fn delete(&self, name: &str) -> Result<()>;
+
+    // Changes whenever `name` is rewritten, None when it does not exist
+    async fn version(&self, name: &str) -> Result<Option<SystemTime>>;
+
+    // Names of the direct children of `prefix`
+    async fn list(&self, prefix: &str) -> Result<Vec<String>>;
+
+    // Streaming access to `name`, None when it does not exist
+    // Backends without direct access hand out a reader over the whole entry read into memory
+    async fn open(&self, name: &str) -> Result<Option<EntryReader>> {
+        Ok(self.read(name).await?.map(buffered))
+    }
+
+    // Guard `name` against accidental overwrites, a no-op where the backend has no such notion
+    async fn protect(&self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    // Short description for logs
+    fn describe(&self) -> String;
+}
+
+// Byte stream over a store entry, `len` is the entry's total size
+pub struct EntryReader {
+    pub len: u64,
+    pub reader: Box<dyn EntryRead>,
+}
+
+pub trait EntryRead: AsyncRead + AsyncSeek + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncSeek + Send + Unpin> EntryRead for T {}
+
+pub fn buffered(bytes: Zeroizing<Vec<u8>>) -> EntryReader {
+    EntryReader {
+        len: bytes.len() as u64,
+        reader: Box::new(Cursor::new(bytes)),
+    }
+}
+
+// Entries holding secret key material (keys or key shares), the ones the sealed store encrypts
+pub fn is_secret(name: &str) -> bool {
+    matches!(name.rsplit('/').next(), Some("client_key" | "client_key_share" | "signer_key"))
+}
+
+// Open the backend selected by KEY_STORE (`fs`, `sealed` or `memory`)
+// Without KEY_STORE the keys are sealed, which needs a passphrase or KEK file: secret
+// keys only go to disk unsealed when KEY_STORE=fs asks for it
+pub async fn open(config: &KmsConfig) -> Result<Arc<dyn KeyStore>> {
+    let sealer = Sealer::from_env(config.kek_file.as_deref()).await?;
+    let kind = config.key_store.as_deref().unwrap_or("sealed");
+    let store: Arc<dyn KeyStore> = match (kind, sealer) {
+        ("fs", sealer) => {
+            if sealer.is_some() {
+                println!("[KeyStore] KEY_STORE=fs, the configured passphrase is not used");
+            }
+            println!("[KeyStore] KEY_STORE=fs, secret keys are stored unsealed");
+            Arc::new(FsStore::new(config.keys_dir.clone()))
+        }
+        ("sealed", Some(sealer)) => {
+            Arc::new(SealedStore::new(Arc::new(FsStore::new(config.keys_dir.clone())), sealer))
+        }
+        ("sealed", None) => bail!(
+            "set KMS_PASSPHRASE or KMS_KEK_FILE to seal the secret keys (or KEY_STORE=fs to store them unsealed)"
+        ),
+        ("memory", _) => {
+            println!("[KeyStore] keys are kept in memory only and lost on restart");
+            Arc::new(MemoryStore::new())
+        }
+        (other, _) => bail!("unknown KEY_STORE {:?}, expected fs, sealed or memory", other),
+    };
+    println!("[KeyStore] using {}", store.describe());
+    Ok(store)
+}
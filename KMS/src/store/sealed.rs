@@ -0,0 +1,147 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::SystemTime;
+use zeroize::Zeroizing;
+use crate::sealing::{is_sealed, Sealer};
+use super::{buffered, is_secret, EntryReader, KeyStore};
+
+// SealedStore encrypts secret entries (client and signer keys) before handing them
+// to the inner store and unseals them in memory on read. Public keys and
+// bookkeeping pass through unchanged.
+pub struct SealedStore {
+    inner: Arc<dyn KeyStore>,
+    sealer: Arc<Sealer>,
+}
+
+impl SealedStore {
+    pub fn new(inner: Arc<dyn KeyStore>, sealer: Sealer) -> Self {
+        Self {
+            inner,
+            sealer: Arc::new(sealer),
+        }
+    }
+
+    // Seal the secret entries still stored in plaintext, returns how many were sealed
+    pub async fn migrate(&self) -> Result<usize> {
+        let mut candidates = vec!["client_key".to_string(), "signer_key".to_string()];
+        for id in self.inner.list("keysets").await? {
+            candidates.push(format!("keysets/{}/client_key", id));
+            candidates.push(format!("keysets/{}/client_key_share", id));
+        }
+
+        let mut sealed = 0;
+        for name in candidates {
+            let Some(bytes) = self.inner.read(&name).await? else {
+                continue;
+            };
+            if is_sealed(&bytes) {
+                continue;
+            }
+            let sealed_bytes = self.seal(&name, bytes).await?;
+            self.inner.write(&name, &sealed_bytes).await?;
+            println!("[SealedStore] sealed {}", name);
+            sealed += 1;
+        }
+        Ok(sealed)
+    }
+
+    // Argon2 takes tens of milliseconds per entry, so (un)sealing runs on the blocking pool
+    async fn seal(&self, name: &str, bytes: Zeroizing<Vec<u8>>) -> Result<Vec<u8>> {
+        let (sealer, label) = (self.sealer.clone(), label(name).to_string());
+        tokio::task::spawn_blocking(move || sealer.seal(&bytes, &label)).await?
+    }
+
+    async fn unseal(&self, name: &str, bytes: Zeroizing<Vec<u8>>) -> Result<Zeroizing<Vec<u8>>> {
+        let (sealer, label) = (self.sealer.clone(), label(name).to_string());
+        tokio::task::spawn_blocking(move || sealer.open(&bytes, &label)).await?
+    }
+}
+
+// The entry name is bound to the ciphertext, a sealed client key can't be swapped in for another entry
+fn label(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+#[async_trait]
+impl KeyStore for SealedStore {
+    async fn read(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        let Some(bytes) = self.inner.read(name).await? else {
+            return Ok(None);
+        };
+        if !is_secret(name) {
+            return Ok(Some(bytes));
+        }
+        // A plaintext secret could have been planted by anyone able to write the
+        // directory, it is only taken over through `KMS migrate-keys`
+        if !is_sealed(&bytes) {
+            bail!("{} is not sealed, run `KMS migrate-keys` to seal it", name);
+        }
+        Ok(Some(self.unseal(name, bytes).await?))
+    }
+
+    async fn write(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        if is_secret(name) {
+            let sealed = self.seal(name, Zeroizing::new(bytes.to_vec())).await?;
+            self.inner.write(name, &sealed).await
+        } else {
+            self.inner.write(name, bytes).await
+        }
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        self.inner.delete(name).await
+    }
+
+    async fn version(&self, name: &str) -> Result<Option<SystemTime>> {
+        self.inner.version(name).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        self.inner.list(prefix).await
+    }
+
+    // Public entries stream straight from the inner store
+    async fn open(&self, name: &str) -> Result<Option<EntryReader>> {
+        if is_secret(name) {
+            return Ok(self.read(name).await?.map(buffered));
+        }
+        self.inner.open(name).await
+    }
+
+    async fn protect(&self, name: &str) -> Result<()> {
+        self.inner.protect(name).await
+    }
+
+    fn describe(&self) -> String {
+        format!("sealed {}", self.inner.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn test_migrate_seals_plaintext_keys() {
+        let inner = Arc::new(MemoryStore::new());
+        inner.write("keysets/k1/client_key", b"secret").await.unwrap();
+        inner.write("keysets/k1/public_key", b"public").await.unwrap();
+
+        let sealer = Sealer::new(Zeroizing::new(b"passphrase".to_vec())).unwrap();
+        let store = SealedStore::new(inner.clone(), sealer);
+        // Plaintext secrets are refused until migrated
+        assert!(store.read("keysets/k1/client_key").await.is_err());
+        assert_eq!(store.migrate().await.unwrap(), 1);
+        // Already sealed entries are left alone
+        assert_eq!(store.migrate().await.unwrap(), 0);
+
+        let raw = inner.read("keysets/k1/client_key").await.unwrap().unwrap();
+        assert!(is_sealed(&raw));
+        let opened = store.read("keysets/k1/client_key").await.unwrap().unwrap();
+        assert_eq!(&**opened, b"secret");
+        let public = inner.read("keysets/k1/public_key").await.unwrap().unwrap();
+        assert_eq!(&**public, b"public");
+    }
+}
@@ -100,12 +100,12 @@ impl EncryptedERC20Client {
         Ok(supply.into())
     }
 
-    // pub async fn balance_of(&self, user: Address) -> Result<[u8; 32]> {
-    //     let provider = self.provider().await?;
-    //     let contract = EncryptedERC20::new(self.contract_address, provider);
-    //     let balance = contract.balanceOf(user).call().await?;
-    //     Ok(balance.0)
-    // }
+    pub async fn balance_of(&self, user: Address) -> Result<[u8; 32]> {
+        let provider = self.provider().await?;
+        let contract = EncryptedERC20::new(self.contract_address, provider);
+        let balance = contract.balanceOf(user).call().await?;
+        Ok(balance.0)
+    }
 }
 
 
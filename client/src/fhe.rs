@@ -1,29 +1,56 @@
+use alloy::primitives::Address;
 use anyhow::Result;
 use sha3::{Digest, Keccak256};
-use tfhe::{prelude::*, ClientKey, CompactPublicKey};
+use tfhe::zk::{CompactPkePublicParams, ZkComputeLoad};
+use tfhe::{prelude::*, ClientKey, CompactPublicKey, ProvenCompactCiphertextList};
 
-/// Encrypt a u64 value using the public key
-pub fn encrypt(value: u64, pk: &CompactPublicKey) -> Result<Vec<u8>> {
-    let ct = tfhe::CompactCiphertextList::builder(pk)
+/// Data a proof is bound to: the contract the input is for, then the user submitting it
+/// The coprocessor rebuilds it from the VerifyInput event, a proof made for another
+/// contract or user does not verify
+pub fn input_metadata(contract: Address, user: Address) -> Vec<u8> {
+    [contract.as_slice(), user.as_slice()].concat()
+}
+
+/// Encrypt a u64 value using the public key, with a zero-knowledge proof that the
+/// ciphertext is well formed, bound to `contract` and `user`
+pub fn encrypt(
+    value: u64,
+    pk: &CompactPublicKey,
+    crs: &CompactPkePublicParams,
+    contract: Address,
+    user: Address,
+) -> Result<Vec<u8>> {
+    let ct = ProvenCompactCiphertextList::builder(pk)
         .push(value)
-        .build();
+        .build_with_proof_packed(crs, &input_metadata(contract, user), ZkComputeLoad::Proof)?;
     Ok(bincode::serialize(&ct)?)
 }
 
 /// Decrypt ciphertext bytes using the client key (for testing only)
 /// @note  This is for testing, in production decryption happens on the coprocessor side
 pub fn decrypt(ciphertext: &[u8], sk: &ClientKey) -> Result<u64> {
-    let ct: tfhe::CompactCiphertextList = bincode::deserialize(ciphertext)?;
-    let expanded = ct.expand()?;
+    let ct: ProvenCompactCiphertextList = bincode::deserialize(ciphertext)?;
+    let expanded = ct.expand_without_verification()?;
     let value: tfhe::FheUint64 = expanded.get(0)?.ok_or(anyhow::anyhow!("No value in ciphertext"))?;
     let decrypted_value: u64 = value.decrypt(sk);
     Ok(decrypted_value)
 }
 
-/// Compute a handle (keccak256 hash) from ciphertext bytes
-/// This handle is used to track ciphertexts on-chain
-pub fn compute_handle(ciphertext_bytes: &[u8]) -> [u8; 32] {
+/// FheType byte of the euint64 values `encrypt` produces
+pub const EUINT64: u8 = 5;
+
+/// Compute the handle of entry `index` of an input list from its ciphertext bytes
+/// Host layout: keccak256 of the list (21 bytes) | index (1) | chain id (8) | FheType (1) | version (1),
+/// the coprocessor takes the entry at `index` out of the list
+pub fn compute_handle(ciphertext_bytes: &[u8], index: u8, chain_id: u64, fhe_type: u8) -> [u8; 32] {
     let mut hasher = Keccak256::new();
     hasher.update(ciphertext_bytes);
-    hasher.finalize().into()
+    let hash: [u8; 32] = hasher.finalize().into();
+
+    let mut handle = [0u8; 32];
+    handle[..21].copy_from_slice(&hash[..21]);
+    handle[21] = index;
+    handle[22..30].copy_from_slice(&chain_id.to_be_bytes());
+    handle[30] = fhe_type;
+    handle
 }
\ No newline at end of file
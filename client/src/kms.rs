@@ -1,22 +1,325 @@
-use anyhow::Result;
+use alloy::primitives::{Address, Bytes, Signature, B256, U256};
+use alloy::signers::{local::PrivateKeySigner, SignerSync};
+use alloy::sol;
+use alloy::sol_types::{eip712_domain, SolStruct};
+use anyhow::{anyhow, Result};
 use base64::Engine;
+use common::fingerprint::check_fingerprint;
+use crypto_box::aead::rand_core::RngCore;
+use crypto_box::aead::OsRng;
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tfhe::safe_serialization::safe_deserialize;
+use tfhe::zk::CompactPkePublicParams;
 use tfhe::CompactPublicKey;
+use crate::reencrypt::ReencryptionKeypair;
+
+/// Upper bound on the serialized CRS, the same the KMS writes it with
+const CRS_SIZE_LIMIT: u64 = 1 << 30;
+
+sol! {
+    // Must match the KMS definition, the KMS recovers the signer from this struct
+    struct UserDecryptRequest {
+        bytes32[] handles;
+        address contractAddress;
+        address userAddress;
+        bytes publicKey;
+        uint256 nonce;
+        uint256 expiry;
+    }
+
+    // Signed by the KMS over public decryption results, checked by KMSVerifier
+    struct PublicDecryptVerification {
+        bytes32[] ctHandles;
+        bytes decryptedResult;
+        bytes extraData;
+    }
+}
+
+/// Signer the KMS publishes at GET /signer
+#[derive(Debug, Deserialize)]
+pub struct KmsSignerInfo {
+    pub address: Address,
+    pub gateway_chain_id: u64,
+    pub verifying_contract: Address,
+}
+
+/// How long a signed user decryption request stays valid
+const REQUEST_TTL_SECS: u64 = 300;
+
+#[derive(Serialize)]
+struct UserDecryptBody {
+    handles: Vec<B256>,
+    contract_address: Address,
+    user_address: Address,
+    public_key: Bytes,
+    nonce: U256,
+    expiry: u64,
+    signature: Bytes,
+}
+
+#[derive(Deserialize)]
+struct UserDecryptResponse {
+    sealed: Vec<SealedPlaintext>,
+}
+
+#[derive(Deserialize)]
+struct SealedPlaintext {
+    handle: B256,
+    sealed: String,
+}
+
+#[derive(Serialize)]
+struct DecryptBody<'a> {
+    handles: &'a [B256],
+}
+
+#[derive(Deserialize)]
+struct DecryptResponse {
+    plaintexts: Vec<Plaintext>,
+    signature: Bytes,
+}
 
 #[derive(Deserialize)]
-pub struct PublicKeyResponse {
-    pub public_key: String,
+struct Plaintext {
+    handle: B256,
+    value: U256,
+}
+
+/// Active public key of the KMS
+pub struct KmsPublicKey {
+    pub key: CompactPublicKey,
+    pub key_id: String,
+    /// Parameter preset of the keyset (GET /params/{name} on the KMS)
+    pub params: String,
+}
+
+/// Fetch the active public key with its keyset id and parameter preset
+/// Uses the binary endpoint, id and preset come in the X-Key-Id and X-Key-Params headers.
+/// With `pinned` set, a key whose fingerprint differs is refused.
+pub async fn fetch_public_key(url: &str, pinned: Option<&str>) -> Result<KmsPublicKey> {
+    let response = Client::new()
+        .get(format!("{}/keys/public/raw", url))
+        .send()
+        .await?
+        .error_for_status()?;
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("KMS response has no {} header", name))
+    };
+    let key_id = header("x-key-id")?;
+    let params = header("x-key-params")?;
+    let bytes = response.bytes().await?;
+    if let Some(pinned) = pinned {
+        check_fingerprint("public key", &bytes, pinned)?;
+    }
+    Ok(KmsPublicKey {
+        key: bincode::deserialize(&bytes)?,
+        key_id,
+        params,
+    })
 }
 
-pub async fn fetch_public_key(url: &str) -> Result<CompactPublicKey> {
-    let response: PublicKeyResponse = Client::new()
-        .get(format!("{}/keys/public", url))
+/// Fetch the CRS of keyset `key_id`, encryptions under its public key are proven against it
+/// With `pinned` set, a CRS whose fingerprint differs is refused
+pub async fn fetch_crs(url: &str, key_id: &str, pinned: Option<&str>) -> Result<CompactPkePublicParams> {
+    let bytes = Client::new()
+        .get(format!("{}/keys/{}/crs/raw", url, key_id))
         .send()
         .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    if let Some(pinned) = pinned {
+        check_fingerprint("CRS", &bytes, pinned)?;
+    }
+    safe_deserialize(bytes.as_ref(), CRS_SIZE_LIMIT).map_err(|e| anyhow!("invalid CRS: {}", e))
+}
+
+/// Build and sign a user decryption request with a random nonce, valid for REQUEST_TTL_SECS
+pub fn sign_user_decrypt(
+    signer: &PrivateKeySigner,
+    chain_id: u64,
+    contract: Address,
+    handles: Vec<B256>,
+    public_key: Bytes,
+) -> Result<(UserDecryptRequest, Signature)> {
+    let mut nonce = [0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+    let expiry = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + REQUEST_TTL_SECS;
+
+    let request = UserDecryptRequest {
+        handles,
+        contractAddress: contract,
+        userAddress: signer.address(),
+        publicKey: public_key,
+        nonce: U256::from_be_bytes(nonce),
+        expiry: U256::from(expiry),
+    };
+    let domain = eip712_domain! {
+        name: "KMS",
+        version: "1",
+        chain_id: chain_id,
+    };
+    let signature = signer.sign_hash_sync(&request.eip712_signing_hash(&domain))?;
+    Ok((request, signature))
+}
+
+/// Ask the KMS to re-encrypt `handles` under a fresh keypair and open the results locally
+/// The signer must be allowed on every handle in the ACL (as must `contract`)
+pub async fn user_decrypt(
+    url: &str,
+    signer: &PrivateKeySigner,
+    chain_id: u64,
+    contract: Address,
+    handles: Vec<B256>,
+) -> Result<Vec<U256>> {
+    let keypair = ReencryptionKeypair::generate();
+    let public_key = Bytes::copy_from_slice(keypair.public_key().as_bytes());
+    let (request, signature) = sign_user_decrypt(signer, chain_id, contract, handles, public_key)?;
+
+    let response = Client::new()
+        .post(format!("{}/decrypt/user", url))
+        .json(&UserDecryptBody {
+            handles: request.handles.clone(),
+            contract_address: contract,
+            user_address: signer.address(),
+            public_key: request.publicKey,
+            nonce: request.nonce,
+            expiry: request.expiry.to(),
+            signature: Bytes::copy_from_slice(&signature.as_bytes()),
+        })
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("KMS returned {}: {}", response.status(), response.text().await?));
+    }
+    let response: UserDecryptResponse = response.json().await?;
+
+    request
+        .handles
+        .iter()
+        .map(|handle| {
+            let sealed = response
+                .sealed
+                .iter()
+                .find(|s| s.handle == *handle)
+                .ok_or_else(|| anyhow!("KMS did not return handle {}", handle))?;
+            let bytes = base64::engine::general_purpose::STANDARD.decode(&sealed.sealed)?;
+            keypair.unseal(&bytes)
+        })
+        .collect()
+}
+
+pub async fn fetch_signer(url: &str) -> Result<KmsSignerInfo> {
+    Ok(Client::new()
+        .get(format!("{}/signer", url))
+        .send()
+        .await?
+        .error_for_status()?
         .json()
+        .await?)
+}
+
+/// Publicly decrypt `handles` through the KMS's /decrypt route (decrypt role `token`)
+/// The plaintexts are only returned once the KMS signature over them checks out against `signer`
+pub async fn public_decrypt(
+    url: &str,
+    token: &str,
+    signer: &KmsSignerInfo,
+    handles: &[B256],
+) -> Result<Vec<U256>> {
+    let response = Client::new()
+        .post(format!("{}/decrypt", url))
+        .bearer_auth(token)
+        .json(&DecryptBody { handles })
+        .send()
         .await?;
-    let bytes = base64::engine::general_purpose::STANDARD.decode(&response.public_key)?;
-    let public_key: CompactPublicKey = bincode::deserialize(&bytes)?;
-    Ok(public_key)
-}
\ No newline at end of file
+    if !response.status().is_success() {
+        return Err(anyhow!("KMS returned {}: {}", response.status(), response.text().await?));
+    }
+    let response: DecryptResponse = response.json().await?;
+
+    let plaintexts = handles
+        .iter()
+        .map(|handle| {
+            response
+                .plaintexts
+                .iter()
+                .find(|p| p.handle == *handle)
+                .map(|p| p.value)
+                .ok_or_else(|| anyhow!("KMS did not return handle {}", handle))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    verify_public_decryption(signer, handles, &plaintexts, &response.signature)?;
+    Ok(plaintexts)
+}
+
+/// Check that `signature` is the KMS signer's signature over the decryption of `handles`
+/// into `plaintexts`, exactly as KMSVerifier would before trusting them on chain
+pub fn verify_public_decryption(
+    signer: &KmsSignerInfo,
+    handles: &[B256],
+    plaintexts: &[U256],
+    signature: &[u8],
+) -> Result<()> {
+    let decrypted_result: Vec<u8> = plaintexts
+        .iter()
+        .flat_map(|value| value.to_be_bytes::<32>())
+        .collect();
+    let message = PublicDecryptVerification {
+        ctHandles: handles.to_vec(),
+        decryptedResult: decrypted_result.into(),
+        extraData: Bytes::new(),
+    };
+    let domain = eip712_domain! {
+        name: "Decryption",
+        version: "1",
+        chain_id: signer.gateway_chain_id,
+        verifying_contract: signer.verifying_contract,
+    };
+    let recovered = Signature::try_from(signature)?
+        .recover_address_from_prehash(&message.eip712_signing_hash(&domain))?;
+    if recovered != signer.address {
+        return Err(anyhow!("decryption signed by {}, expected KMS signer {}", recovered, signer.address));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_public_decryption() {
+        let kms = PrivateKeySigner::random();
+        let info = KmsSignerInfo {
+            address: kms.address(),
+            gateway_chain_id: 31337,
+            verifying_contract: Address::ZERO,
+        };
+        let handles = [B256::repeat_byte(1)];
+        let plaintexts = [U256::from(42)];
+
+        let message = PublicDecryptVerification {
+            ctHandles: handles.to_vec(),
+            decryptedResult: U256::from(42).to_be_bytes::<32>().to_vec().into(),
+            extraData: Bytes::new(),
+        };
+        let domain = eip712_domain! {
+            name: "Decryption",
+            version: "1",
+            chain_id: 31337,
+            verifying_contract: Address::ZERO,
+        };
+        let signature = kms.sign_hash_sync(&message.eip712_signing_hash(&domain)).unwrap();
+
+        assert!(verify_public_decryption(&info, &handles, &plaintexts, &signature.as_bytes()).is_ok());
+        assert!(verify_public_decryption(&info, &handles, &[U256::from(43)], &signature.as_bytes()).is_err());
+    }
+}
@@ -1,9 +1,11 @@
 mod contracts;
 mod fhe;
 mod kms;
+mod reencrypt;
 
 use alloy::primitives::Address;
 use anyhow::Result;
+use common::deployments;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -12,9 +14,18 @@ async fn main() -> Result<()> {
     let rpc_url = std::env::var("RPC_URL").unwrap_or_else(|_| "http://127.0.0.1:8545".into());
     let private_key = std::env::var("PRIVATE_KEY")
         .unwrap_or_else(|_| "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80".into());
-    let contract_address: Address = std::env::var("CONTRACT_ADDRESS")
-        .unwrap_or_else(|_| "0x5FbDB2315678afecb367f032d93F642f64180aa3".into())
+    let chain_id: u64 = std::env::var("CHAIN_ID")
+        .unwrap_or_else(|_| "31337".into())
         .parse()?;
+    let contracts_dir = std::env::var("CONTRACTS_DIR").unwrap_or_else(|_| "../contracts".into());
+    let deployed = deployments::load_deployments(std::path::Path::new(&contracts_dir), chain_id)?;
+    // CONTRACT_ADDRESS overrides the EncryptedERC20 address picked from the broadcast files
+    let contract_address: Address = match std::env::var("CONTRACT_ADDRESS") {
+        Ok(address) => address.parse()?,
+        Err(_) => deployed
+            .encrypted_erc20
+            .unwrap_or("0x5FbDB2315678afecb367f032d93F642f64180aa3".parse()?),
+    };
     let recipient: Address = std::env::var("RECIPIENT")
         .unwrap_or_else(|_| "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".into())
         .parse()?;
@@ -27,19 +38,63 @@ async fn main() -> Result<()> {
 
     // --- Step 1: Fetch public key from KMS ---
     println!("[1] Fetching public key from KMS at {}", kms_url);
-    let pk = kms::fetch_public_key(&kms_url).await?;
-    println!("    ✓ Public key fetched successfully");
+    // KMS_PUBLIC_KEY_FINGERPRINT pins the public key, anything else the KMS serves is refused
+    let pinned = std::env::var("KMS_PUBLIC_KEY_FINGERPRINT").ok();
+    let pk = kms::fetch_public_key(&kms_url, pinned.as_deref()).await?;
+    println!(
+        "    ✓ Public key fetched successfully (keyset {}, parameter preset: {})",
+        pk.key_id, pk.params
+    );
+    if pinned.is_some() {
+        println!("    ✓ Fingerprint matches KMS_PUBLIC_KEY_FINGERPRINT");
+    }
+    // TFHE_PARAMS pins the preset this client expects the KMS keys to use
+    if let Ok(expected) = std::env::var("TFHE_PARAMS")
+        && expected != pk.params
+    {
+        anyhow::bail!("KMS keys use the {} parameter preset, expected {}", pk.params, expected);
+    }
+    // KMS_CRS_FINGERPRINT pins the CRS the same way
+    let pinned_crs = std::env::var("KMS_CRS_FINGERPRINT").ok();
+    let crs = kms::fetch_crs(&kms_url, &pk.key_id, pinned_crs.as_deref()).await?;
+    println!("    ✓ CRS fetched for proven encryption");
+    if pinned_crs.is_some() {
+        println!("    ✓ Fingerprint matches KMS_CRS_FINGERPRINT");
+    }
+    let kms_signer = match kms::fetch_signer(&kms_url).await {
+        Ok(kms_signer) => {
+            println!("    KMS signer: {} (gateway chain {})", kms_signer.address, kms_signer.gateway_chain_id);
+            // The KMS signs for one KMSVerifier, its signatures are useless against any other
+            if let Some(kms_verifier) = deployed.kms_verifier
+                && kms_verifier != kms_signer.verifying_contract
+            {
+                anyhow::bail!(
+                    "KMS signs for KMSVerifier {}, but {} is deployed",
+                    kms_signer.verifying_contract,
+                    kms_verifier
+                );
+            }
+            Some(kms_signer)
+        }
+        Err(e) => {
+            println!("    KMS signer: (error: {})", e);
+            None
+        }
+    };
     println!();
 
     // --- Step 2: Encrypt the amount ---
+    // The proof is bound to the token contract and to us as sender
     println!("[2] Encrypting amount using public key: {}", amount);
-    let ciphertext = fhe::encrypt(amount, &pk)?;
+    let signer: alloy::signers::local::PrivateKeySigner = private_key.parse()?;
+    let ciphertext = fhe::encrypt(amount, &pk.key, &crs, contract_address, signer.address())?;
+    println!("    ✓ Proven for contract {} and user {}", contract_address, signer.address());
     println!("    ✓ Ciphertext size: {} bytes", ciphertext.len());
     println!();
 
     // --- Step 3: Compute handle ---
-    println!("[3] Computing handle (keccak256 of ciphertext)");
-    let handle = fhe::compute_handle(&ciphertext);
+    println!("[3] Computing handle (keccak256 of ciphertext, host layout)");
+    let handle = fhe::compute_handle(&ciphertext, 0, chain_id, fhe::EUINT64);
     println!("    ✓ Handle: 0x{}", hex::encode(handle));
     println!();
 
@@ -58,6 +113,10 @@ async fn main() -> Result<()> {
         private_key.clone(),
     );
     println!("    Contract: {}", contract_address);
+    println!("    ACL: {:?}", deployed.acl);
+    println!("    FHEVMExecutor: {:?}", deployed.fhevm_executor);
+    println!("    KMSVerifier: {:?}", deployed.kms_verifier);
+    println!("    InputVerifier: {:?}", deployed.input_verifier);
     println!();
 
     // --- Step 6: Query contract info ---
@@ -88,6 +147,40 @@ async fn main() -> Result<()> {
         Err(e) => println!("    ✗ Transfer failed: {}", e),
     }
 
+    println!();
+
+    // --- Step 8: Read own balance through the KMS (re-encrypted to us) ---
+    println!("[8] Decrypting own balance via KMS re-encryption");
+    match client.balance_of(signer.address()).await {
+        Ok(balance_handle) => {
+            println!("    Balance handle: 0x{}", hex::encode(balance_handle));
+            match kms::user_decrypt(&kms_url, &signer, chain_id, contract_address, vec![balance_handle.into()]).await {
+                Ok(balance) => println!("    ✓ Balance: {}", balance[0]),
+                Err(e) => println!("    ✗ User decryption failed: {}", e),
+            }
+        }
+        Err(e) => println!("    ✗ balanceOf failed: {}", e),
+    }
+
+    // --- Step 9: Public decryption, checked as KMSVerifier would ---
+    // Needs the KMS's decrypt role token (KMS_DECRYPT_TOKEN), skipped without it
+    if let (Ok(token), Some(kms_signer)) = (std::env::var("KMS_DECRYPT_TOKEN"), &kms_signer) {
+        println!();
+        println!("[9] Publicly decrypting balance via KMS");
+        match client.balance_of(signer.address()).await {
+            Ok(balance_handle) => {
+                match kms::public_decrypt(&kms_url, &token, kms_signer, &[balance_handle.into()]).await {
+                    Ok(balance) => {
+                        println!("    ✓ Balance: {}", balance[0]);
+                        println!("    ✓ Signed by KMS signer {}", kms_signer.address);
+                    }
+                    Err(e) => println!("    ✗ Public decryption failed: {}", e),
+                }
+            }
+            Err(e) => println!("    ✗ balanceOf failed: {}", e),
+        }
+    }
+
     println!();
     println!("=== Demo Complete ===");
 
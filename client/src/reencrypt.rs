@@ -0,0 +1,34 @@
+use alloy::primitives::U256;
+use anyhow::{anyhow, Result};
+use crypto_box::aead::OsRng;
+use crypto_box::{PublicKey, SecretKey};
+
+/// Ephemeral X25519 keypair the KMS seals user decryptions to
+/// The secret key never leaves the client, so only this client can read the result
+pub struct ReencryptionKeypair {
+    secret: SecretKey,
+}
+
+impl ReencryptionKeypair {
+    pub fn generate() -> Self {
+        Self {
+            secret: SecretKey::generate(&mut OsRng),
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.secret.public_key()
+    }
+
+    /// Open a sealed box returned by the KMS into the 32 byte big-endian plaintext
+    pub fn unseal(&self, sealed: &[u8]) -> Result<U256> {
+        let plaintext = self
+            .secret
+            .unseal(sealed)
+            .map_err(|_| anyhow!("sealed box could not be opened with this key"))?;
+        let bytes: [u8; 32] = plaintext
+            .try_into()
+            .map_err(|_| anyhow!("unexpected plaintext length"))?;
+        Ok(U256::from_be_bytes(bytes))
+    }
+}
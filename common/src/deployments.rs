@@ -0,0 +1,221 @@
+//! Deployment Artifacts
+//! Resolves contract addresses from Foundry `broadcast/*/<chainId>/run-latest.json` files
+//! and the deterministic constants in FHEVMHostAddresses.sol, so addresses don't have to be
+//! copied into `.env` by hand after every deploy.
+
+use alloy_primitives::Address;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Location of the host address constants inside the contracts project
+const HOST_ADDRESSES_PATH: &str = "lib/fhevm/host-contracts/addresses/FHEVMHostAddresses.sol";
+
+/// Addresses of the contracts the coprocessor and client care about on a given chain
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeployedAddresses {
+    pub acl: Option<Address>,
+    pub fhevm_executor: Option<Address>,
+    pub kms_verifier: Option<Address>,
+    pub input_verifier: Option<Address>,
+    pub encrypted_erc20: Option<Address>,
+    /// Decryption callback target (MockGateway on local chains)
+    pub gateway: Option<Address>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BroadcastRun {
+    #[serde(default)]
+    transactions: Vec<BroadcastTx>,
+    #[serde(default)]
+    timestamp: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BroadcastTx {
+    transaction_type: Option<String>,
+    contract_name: Option<String>,
+    contract_address: Option<Address>,
+}
+
+/// Resolve deployed addresses for `chain_id` from a Foundry project directory
+///
+/// Starts from the FHEVMHostAddresses.sol constants (if the fhevm submodule is checked out),
+/// then replays every broadcast run for the chain in chronological order so the most recent
+/// deployment wins:
+/// - MockACL / MockFHEVMExecutor / MockGateway / EncryptedERC20 use the address they were created at
+/// - ACL / FHEVMExecutor / KMSVerifier / InputVerifier are implementations behind proxies,
+///   so a DeployInfra run points those back at the deterministic proxy addresses
+pub fn load_deployments(contracts_dir: &Path, chain_id: u64) -> Result<DeployedAddresses> {
+    let host = match fs::read_to_string(contracts_dir.join(HOST_ADDRESSES_PATH)) {
+        Ok(source) => parse_host_addresses(&source),
+        Err(_) => DeployedAddresses::default(),
+    };
+
+    let mut addresses = host.clone();
+    for run in broadcast_runs(contracts_dir, chain_id)? {
+        for tx in run.transactions {
+            if tx.transaction_type.as_deref() != Some("CREATE") {
+                continue;
+            }
+            let (Some(name), Some(address)) = (tx.contract_name, tx.contract_address) else {
+                continue;
+            };
+            match name.as_str() {
+                "MockACL" => addresses.acl = Some(address),
+                "MockFHEVMExecutor" => addresses.fhevm_executor = Some(address),
+                "EncryptedERC20" => addresses.encrypted_erc20 = Some(address),
+                "MockGateway" => addresses.gateway = Some(address),
+                "ACL" => addresses.acl = host.acl,
+                "FHEVMExecutor" => addresses.fhevm_executor = host.fhevm_executor,
+                "KMSVerifier" => addresses.kms_verifier = host.kms_verifier,
+                "InputVerifier" => addresses.input_verifier = host.input_verifier,
+                _ => {}
+            }
+        }
+    }
+
+    Ok(addresses)
+}
+
+/// Parse `address constant <name> = 0x...;` lines from FHEVMHostAddresses.sol
+pub fn parse_host_addresses(source: &str) -> DeployedAddresses {
+    let mut addresses = DeployedAddresses::default();
+    for line in source.lines() {
+        let Some(rest) = line.trim().strip_prefix("address constant ") else {
+            continue;
+        };
+        let Some((name, value)) = rest.split_once('=') else {
+            continue;
+        };
+        let Ok(address) = value.trim().trim_end_matches(';').trim().parse::<Address>() else {
+            continue;
+        };
+        match name.trim() {
+            "aclAdd" => addresses.acl = Some(address),
+            "fhevmExecutorAdd" => addresses.fhevm_executor = Some(address),
+            "kmsVerifierAdd" => addresses.kms_verifier = Some(address),
+            "inputVerifierAdd" => addresses.input_verifier = Some(address),
+            _ => {}
+        }
+    }
+    addresses
+}
+
+/// Read every `broadcast/<script>/<chain_id>/run-latest.json`, oldest first
+fn broadcast_runs(contracts_dir: &Path, chain_id: u64) -> Result<Vec<BroadcastRun>> {
+    let broadcast_dir = contracts_dir.join("broadcast");
+    if !broadcast_dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(&broadcast_dir)? {
+        let path: PathBuf = entry?
+            .path()
+            .join(chain_id.to_string())
+            .join("run-latest.json");
+        if !path.is_file() {
+            continue;
+        }
+        let raw = fs::read_to_string(&path)?;
+        let run: BroadcastRun = serde_json::from_str(&raw)
+            .with_context(|| format!("Failed to parse broadcast file {}", path.display()))?;
+        runs.push(run);
+    }
+    runs.sort_by_key(|run| run.timestamp);
+    Ok(runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST_SOURCE: &str = r#"
+// SPDX-License-Identifier: BSD-3-Clause-Clear
+pragma solidity ^0.8.24;
+
+address constant aclAdd = 0x339EcE85B9E11a3A3AA557582784a15d7F82AAf2;
+address constant fhevmExecutorAdd = 0x05fD9B5EFE0a996095f42Ed7e77c390810CF660c;
+address constant kmsVerifierAdd = 0x12B064FB845C1cc05e9493856a1D637a73e944bE;
+address constant inputVerifierAdd = 0x3a2DA6f1daE9eF988B48d9CF27523FA31a8eBE50;
+"#;
+
+    fn write_run(dir: &Path, script: &str, chain_id: u64, body: &str) {
+        let run_dir = dir.join("broadcast").join(script).join(chain_id.to_string());
+        fs::create_dir_all(&run_dir).unwrap();
+        fs::write(run_dir.join("run-latest.json"), body).unwrap();
+    }
+
+    #[test]
+    fn test_latest_broadcast_wins() {
+        let dir = std::env::temp_dir().join(format!("deployments-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let host_path = dir.join(HOST_ADDRESSES_PATH);
+        fs::create_dir_all(host_path.parent().unwrap()).unwrap();
+        fs::write(&host_path, HOST_SOURCE).unwrap();
+
+        write_run(
+            &dir,
+            "MockTest.s.sol",
+            31337,
+            r#"{"timestamp": 100, "transactions": [
+                {"transactionType": "CREATE", "contractName": "MockACL", "contractAddress": "0x5FbDB2315678afecb367f032d93F642f64180aa3"},
+                {"transactionType": "CREATE", "contractName": "MockFHEVMExecutor", "contractAddress": "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512"}
+            ]}"#,
+        );
+        write_run(
+            &dir,
+            "DeployToken.s.sol",
+            31337,
+            r#"{"timestamp": 200, "transactions": [
+                {"transactionType": "CREATE", "contractName": "EncryptedERC20", "contractAddress": "0x9fE46736679d2D9a65F0992F2272dE9f3c7fa6e0"},
+                {"transactionType": "CALL", "contractName": "EncryptedERC20", "contractAddress": "0x9fE46736679d2D9a65F0992F2272dE9f3c7fa6e0"}
+            ]}"#,
+        );
+        // Other chains are ignored
+        write_run(
+            &dir,
+            "DeployToken.s.sol",
+            11155111,
+            r#"{"timestamp": 300, "transactions": [
+                {"transactionType": "CREATE", "contractName": "EncryptedERC20", "contractAddress": "0xCf7Ed3AccA5a467e9e704C703E8D87F634fB0Fc9"}
+            ]}"#,
+        );
+
+        let addresses = load_deployments(&dir, 31337).unwrap();
+        let host = parse_host_addresses(HOST_SOURCE);
+        assert_eq!(
+            addresses.acl,
+            Some("0x5FbDB2315678afecb367f032d93F642f64180aa3".parse().unwrap())
+        );
+        assert_eq!(
+            addresses.fhevm_executor,
+            Some("0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512".parse().unwrap())
+        );
+        assert_eq!(
+            addresses.encrypted_erc20,
+            Some("0x9fE46736679d2D9a65F0992F2272dE9f3c7fa6e0".parse().unwrap())
+        );
+        assert_eq!(addresses.kms_verifier, host.kms_verifier);
+        assert_eq!(addresses.input_verifier, host.input_verifier);
+
+        // A newer DeployInfra run points ACL/Executor back at the deterministic proxies
+        write_run(
+            &dir,
+            "DeployInfra.s.sol",
+            31337,
+            r#"{"timestamp": 400, "transactions": [
+                {"transactionType": "CREATE", "contractName": "ACL", "contractAddress": "0xDc64a140Aa3E981100a9becA4E685f962f0cF6C9"},
+                {"transactionType": "CREATE", "contractName": "FHEVMExecutor", "contractAddress": "0x5FC8d32690cc91D4c39d9d3abcBD16989F875707"}
+            ]}"#,
+        );
+        let addresses = load_deployments(&dir, 31337).unwrap();
+        assert_eq!(addresses.acl, host.acl);
+        assert_eq!(addresses.fhevm_executor, host.fhevm_executor);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+//! Key Fingerprints
+//! Keys downloaded from the KMS can be pinned to the sha256 or keccak256 hex that
+//! GET /keys/{id}/info lists for them, a key that hashes to neither is refused.
+use alloy_primitives::{hex, keccak256};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// A downloaded key doesn't match its pinned fingerprint
+/// Retrying won't help: either the pin or the KMS's keyset has to change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FingerprintMismatch {
+    pub name: String,
+    pub pinned: String,
+    pub sha256: String,
+    pub keccak256: String,
+}
+
+impl fmt::Display for FingerprintMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} fingerprint mismatch: expected {}, got sha256 {} / keccak256 {}",
+            self.name, self.pinned, self.sha256, self.keccak256
+        )
+    }
+}
+
+impl std::error::Error for FingerprintMismatch {}
+
+/// Compare the downloaded `name` key with the pinned sha256 or keccak256 hex
+pub fn check_fingerprint(name: &str, bytes: &[u8], pinned: &str) -> Result<(), FingerprintMismatch> {
+    let pinned = pinned.trim().trim_start_matches("0x").to_lowercase();
+    let sha256 = hex::encode(Sha256::digest(bytes));
+    let keccak256 = hex::encode(keccak256(bytes));
+    if pinned != sha256 && pinned != keccak256 {
+        return Err(FingerprintMismatch {
+            name: name.to_string(),
+            pinned,
+            sha256,
+            keccak256,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_fingerprint() {
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let keccak = "0x4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45";
+        assert!(check_fingerprint("key", b"abc", sha256).is_ok());
+        assert!(check_fingerprint("key", b"abc", &keccak.to_uppercase().replace("0X", "0x")).is_ok());
+        let mismatch = check_fingerprint("key", b"abd", keccak).unwrap_err();
+        assert_eq!(mismatch.pinned, keccak.trim_start_matches("0x"));
+    }
+}
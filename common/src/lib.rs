@@ -0,0 +1,3 @@
+//! Code shared by the coprocessor and the client
+pub mod deployments;
+pub mod fingerprint;
@@ -4,6 +4,7 @@ pragma solidity ^0.8.24;
 import {Script, console} from "forge-std/Script.sol";
 import {MockFHEVMExecutor} from "../src/mocks/MockFHEVMExecutor.sol";
 import {MockACL} from "../src/mocks/MockACL.sol";
+import {MockGateway} from "../src/mocks/MockGateway.sol";
 
 /**
  * @title DeployMocks
@@ -27,10 +28,13 @@ contract DeployMocks is Script {
 
         mockAcl.setFHEVMExecutor(address(mockExecutor));
 
+        MockGateway mockGateway = new MockGateway();
+
         vm.stopBroadcast();
 
         console.log("  MockACL:", address(mockAcl));
         console.log("  MockFHEVMExecutor:", address(mockExecutor));
+        console.log("  MockGateway:", address(mockGateway));
         console.log("\nUpdate coprocessor/.env:");
         console.log("  TFHE_EXECUTOR_ADDRESS=%s", address(mockExecutor));
         console.log("  ACL_ADDRESS=%s", address(mockAcl));
@@ -109,8 +113,11 @@ contract E2EMockTest is Script {
         acl.setFHEVMExecutor(address(executor));
 
         console.log("Deployed:");
+        MockGateway gateway = new MockGateway();
+
         console.log("  MockACL:", address(acl));
         console.log("  MockFHEVMExecutor:", address(executor));
+        console.log("  MockGateway:", address(gateway));
 
         // Generate events
         console.log("\nGenerating FHE events...");
@@ -132,6 +139,11 @@ contract E2EMockTest is Script {
         bytes32 newFrom = executor.fheSub(bal2, actual, bytes1(0x00));
         bytes32 newTo = executor.fheAdd(zero, actual, bytes1(0x00));
 
+        // Reveal the sender's new balance through the decryption oracle
+        bytes32[] memory reveal = new bytes32[](1);
+        reveal[0] = newFrom;
+        acl.allowForDecryption(reveal);
+
         vm.stopBroadcast();
 
         console.log("\n================================================================");
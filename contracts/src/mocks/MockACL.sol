@@ -45,6 +45,11 @@ contract MockACL {
         return true;
     }
 
+    /// @notice Check if a handle was made publicly decryptable - always returns true in mock
+    function isAllowedForDecryption(bytes32) external pure returns (bool) {
+        return true;
+    }
+
     /// @notice Check if sender is allowed - always returns true in mock
     function allowedOnBehalf(address, bytes32, address, address) external pure returns (bool) {
         return true;
@@ -38,9 +38,11 @@ contract MockFHEVMExecutor {
     }
 
     /// @notice Generate a unique handle for ciphertext
+    /// @dev Follows the host handle layout: the FheType sits in byte 30 so the coprocessor
+    ///      can price operations by operand type
     function _nextHandle(FheType fheType) internal returns (bytes32) {
         _handleCounter++;
-        return bytes32(uint256(fheType) << 248 | _handleCounter);
+        return bytes32(_handleCounter << 16 | uint256(uint8(fheType)) << 8);
     }
 
     // ===== FHE Operations (mock implementations that emit events) =====
@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.24;
+
+interface IKMSVerifier {
+    function verifyDecryptionEIP712KMSSignatures(
+        bytes32[] memory handlesList,
+        bytes memory decryptedResult,
+        bytes memory decryptionProof
+    ) external returns (bool);
+}
+
+/**
+ * @title MockGateway
+ * @notice Receives public decryption results from the coprocessor's decryption oracle
+ * @dev Used for local testing - KMS signatures are only checked once a KMSVerifier is set
+ */
+contract MockGateway {
+    event DecryptionFulfilled(address indexed relayer, bytes32[] handles, uint256[] plaintexts);
+
+    mapping(bytes32 => uint256) public plaintextOf;
+    mapping(bytes32 => bool) public isDecrypted;
+
+    /// @notice KMSVerifier checking decryption proofs, address(0) accepts any proof
+    address public kmsVerifier;
+
+    function setKMSVerifier(address _kmsVerifier) external {
+        kmsVerifier = _kmsVerifier;
+    }
+
+    /// @notice Callback with decrypted values for handles allowed for decryption
+    /// @param handles The handles that were decrypted
+    /// @param plaintexts Decrypted values, same order as handles
+    /// @param decryptionProof KMS signatures over the results (KMSVerifier format)
+    function fulfillDecryption(bytes32[] calldata handles, uint256[] calldata plaintexts, bytes calldata decryptionProof)
+        external
+    {
+        require(handles.length == plaintexts.length, "MockGateway: length mismatch");
+        if (kmsVerifier != address(0)) {
+            bytes memory decryptedResult = abi.encodePacked(plaintexts);
+            require(
+                IKMSVerifier(kmsVerifier).verifyDecryptionEIP712KMSSignatures(handles, decryptedResult, decryptionProof),
+                "MockGateway: invalid KMS signatures"
+            );
+        }
+
+        for (uint256 i = 0; i < handles.length; i++) {
+            plaintextOf[handles[i]] = plaintexts[i];
+            isDecrypted[handles[i]] = true;
+        }
+
+        emit DecryptionFulfilled(msg.sender, handles, plaintexts);
+    }
+}
@@ -1,28 +1,149 @@
+use crate::hcu::DEFAULT_TX_HCU_LIMIT;
+use crate::policy::CallerPolicy;
+use crate::server_key::{KeyPins, DEFAULT_TFHE_PARAMS};
 use alloy::primitives::Address;
-use anyhow::Context;
+use anyhow::{anyhow, Context};
+use common::deployments::{self, DeployedAddresses};
 use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub websocket_url: String,
     pub tfhe_executor_address: Address,
     pub acl_address: Address,
+    pub chain_id: u64,
+    pub deployments: DeployedAddresses,
+    /// Blocks a result must be buried under before it is final (0 = final immediately)
+    pub confirmation_depth: u64,
+    /// Which callers are served, at which priority and budget
+    pub caller_policy: CallerPolicy,
+    /// Transactions above this many HCU are flagged
+    pub hcu_tx_limit: u64,
+    pub status_port: u16,
+    /// Where results and the processed event index are persisted (DATA_DIR)
+    pub data_dir: PathBuf,
+    /// KMS the server key, public key and CRS are fetched from at startup (KMS_URL)
+    pub kms_url: String,
+    /// Bearer token for the KMS's coprocessor routes (KMS_API_TOKEN)
+    pub kms_token: Option<String>,
+    /// Bearer token the KMS presents on /ciphertexts (COPROCESSOR_API_TOKEN), without it
+    /// no ciphertext is served
+    pub ciphertext_token: Option<String>,
+    /// Expected sha256 or keccak256 of the downloaded keys (KMS_SERVER_KEY_FINGERPRINT,
+    /// KMS_PUBLIC_KEY_FINGERPRINT, KMS_CRS_FINGERPRINT), any other key is refused
+    pub key_pins: KeyPins,
+    /// Parameter preset the KMS's keyset must use (TFHE_PARAMS, same names as the KMS)
+    pub tfhe_params: String,
+    /// Public decryption oracle, disabled unless KMS_URL, ORACLE_PRIVATE_KEY and a gateway are set
+    pub oracle: Option<OracleConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OracleConfig {
+    pub kms_url: String,
+    /// Bearer token of the KMS's decrypt role (KMS_DECRYPT_TOKEN), the KMS refuses a
+    /// token that also grants the coprocessor role
+    pub kms_token: Option<String>,
+    pub private_key: String,
+    pub gateway_address: Address,
+    /// How long to wait for a handle's ciphertext to become final
+    pub timeout: Duration,
 }
 
 pub fn load_config() -> Result<Config, anyhow::Error> {
     dotenv::dotenv().ok();
 
     let websocket_url = env::var("WEBSOCKET_URL").context("WEBSOCKET_URL not set")?;
-    let tfhe_executor_address = env::var("TFHE_EXECUTOR_ADDRESS")
-        .context("TFHE_EXECUTOR_ADDRESS not set")?
-        .parse::<Address>()?;
-    let acl_address = env::var("ACL_ADDRESS")
-        .context("ACL_ADDRESS not set")?
-        .parse::<Address>()?;
+    let chain_id = env::var("CHAIN_ID")
+        .unwrap_or_else(|_| "31337".to_string())
+        .parse::<u64>()
+        .context("CHAIN_ID must be a number")?;
+    let contracts_dir =
+        PathBuf::from(env::var("CONTRACTS_DIR").unwrap_or_else(|_| "../contracts".to_string()));
+    let deployments = deployments::load_deployments(&contracts_dir, chain_id)?;
+
+    // Explicit env vars take precedence over Foundry artifacts
+    let tfhe_executor_address = address_from_env("TFHE_EXECUTOR_ADDRESS", deployments.fhevm_executor)?;
+    let acl_address = address_from_env("ACL_ADDRESS", deployments.acl)?;
+    let confirmation_depth = env::var("CONFIRMATION_DEPTH")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse::<u64>()
+        .context("CONFIRMATION_DEPTH must be a number")?;
+    // Without a policy file every caller is served at normal priority
+    let caller_policy = match env::var("CALLER_POLICY_PATH") {
+        Ok(path) => CallerPolicy::load(path.as_ref())?,
+        Err(_) => CallerPolicy::default(),
+    };
+    let hcu_tx_limit = match env::var("HCU_TX_LIMIT") {
+        Ok(value) => value.parse::<u64>().context("HCU_TX_LIMIT must be a number")?,
+        Err(_) => DEFAULT_TX_HCU_LIMIT,
+    };
+    let status_port = env::var("STATUS_PORT")
+        .unwrap_or_else(|_| "4000".to_string())
+        .parse::<u16>()
+        .context("STATUS_PORT must be a port number")?;
+    // Every input proof is verified against the KMS's public key and CRS
+    let kms_url = env::var("KMS_URL").context("KMS_URL not set")?.trim_end_matches('/').to_string();
+    let oracle = load_oracle_config(deployments.gateway)?;
 
     Ok(Config {
         websocket_url,
         tfhe_executor_address,
         acl_address,
+        chain_id,
+        deployments,
+        confirmation_depth,
+        caller_policy,
+        hcu_tx_limit,
+        status_port,
+        data_dir: PathBuf::from(env::var("DATA_DIR").unwrap_or_else(|_| "./data".to_string())),
+        kms_url,
+        kms_token: env::var("KMS_API_TOKEN").ok(),
+        ciphertext_token: env::var("COPROCESSOR_API_TOKEN").ok(),
+        key_pins: KeyPins {
+            server_key: env::var("KMS_SERVER_KEY_FINGERPRINT").ok(),
+            public_key: env::var("KMS_PUBLIC_KEY_FINGERPRINT").ok(),
+            crs: env::var("KMS_CRS_FINGERPRINT").ok(),
+        },
+        tfhe_params: env::var("TFHE_PARAMS").unwrap_or_else(|_| DEFAULT_TFHE_PARAMS.to_string()),
+        oracle,
     })
 }
+
+fn load_oracle_config(gateway: Option<Address>) -> Result<Option<OracleConfig>, anyhow::Error> {
+    let (Ok(kms_url), Ok(private_key)) = (env::var("KMS_URL"), env::var("ORACLE_PRIVATE_KEY")) else {
+        return Ok(None);
+    };
+    let gateway_address = match env::var("GATEWAY_ADDRESS") {
+        Ok(value) => value.parse::<Address>().context("GATEWAY_ADDRESS is not a valid address")?,
+        Err(_) => match gateway {
+            Some(address) => address,
+            None => return Ok(None),
+        },
+    };
+    let timeout = env::var("DECRYPTION_TIMEOUT_SECS")
+        .unwrap_or_else(|_| "120".to_string())
+        .parse::<u64>()
+        .context("DECRYPTION_TIMEOUT_SECS must be a number")?;
+
+    Ok(Some(OracleConfig {
+        kms_url: kms_url.trim_end_matches('/').to_string(),
+        kms_token: env::var("KMS_DECRYPT_TOKEN").ok(),
+        private_key,
+        gateway_address,
+        timeout: Duration::from_secs(timeout),
+    }))
+}
+
+fn address_from_env(name: &str, fallback: Option<Address>) -> Result<Address, anyhow::Error> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse::<Address>()
+            .with_context(|| format!("{} is not a valid address", name)),
+        Err(_) => fallback.ok_or_else(|| {
+            anyhow!("{} not set and no deployment artifact found under CONTRACTS_DIR", name)
+        }),
+    }
+}
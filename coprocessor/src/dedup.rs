@@ -0,0 +1,166 @@
+//! Processed Event Index
+//! Remembers which logs were already handled, keyed by (tx_hash, log_index), so backfills,
+//! reconnects and replays never re-execute an operation or overwrite its result.
+//! The index is persisted as JSON next to the result store, so both survive a restart
+//! together. Entries from finalized blocks are pruned, a replay of those is caught by its
+//! final result in the store.
+
+use crate::events::types::EventMetadata;
+use alloy::primitives::B256;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProcessedEvent {
+    tx_hash: B256,
+    log_index: u64,
+    block_number: u64,
+    block_hash: Option<B256>,
+}
+
+#[derive(Default)]
+pub struct ProcessedEvents {
+    /// Where the index is persisted, None keeps it in memory only
+    path: Option<PathBuf>,
+    events: HashMap<(B256, u64), ProcessedEvent>,
+}
+
+impl ProcessedEvents {
+    /// In-memory index, lost on restart
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the index persisted at `path`, starting empty if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let events: Vec<ProcessedEvent> = match fs::read(path) {
+            Ok(raw) => serde_json::from_slice(&raw)
+                .with_context(|| format!("Corrupt processed event index {}", path.display()))?,
+            Err(_) => Vec::new(),
+        };
+        println!("[Dedup] loaded {} processed event(s)", events.len());
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            events: events
+                .into_iter()
+                .map(|e| ((e.tx_hash, e.log_index), e))
+                .collect(),
+        })
+    }
+
+    /// Whether the log behind `metadata` was already processed
+    /// Logs without a tx hash (pending) can't be deduplicated and are never reported as seen
+    pub fn is_processed(&self, metadata: &EventMetadata) -> bool {
+        let Some(tx_hash) = metadata.tx_hash else {
+            return false;
+        };
+        self.events.contains_key(&(tx_hash, metadata.log_index))
+    }
+
+    /// Record a processed log and persist the index
+    pub fn mark_processed(&mut self, metadata: &EventMetadata, block_hash: Option<B256>) -> Result<()> {
+        let Some(tx_hash) = metadata.tx_hash else {
+            return Ok(());
+        };
+        self.events.insert(
+            (tx_hash, metadata.log_index),
+            ProcessedEvent {
+                tx_hash,
+                log_index: metadata.log_index,
+                block_number: metadata.block_number,
+                block_hash,
+            },
+        );
+        self.persist()
+    }
+
+    /// Forget logs from a block that was reorged out so their re-inclusion is processed again
+    pub fn forget_block(&mut self, block_hash: Option<B256>) -> Result<usize> {
+        let before = self.events.len();
+        self.events.retain(|_, e| e.block_hash != block_hash);
+        let removed = before - self.events.len();
+        if removed > 0 {
+            self.persist()?;
+        }
+        Ok(removed)
+    }
+
+    /// Drop entries of blocks below the finalized one
+    /// Only logs that were processed are dropped, a late log of an old block is still new
+    pub fn prune_below(&mut self, finalized: u64) -> Result<usize> {
+        let before = self.events.len();
+        self.events.retain(|_, e| e.block_number >= finalized);
+        let removed = before - self.events.len();
+        if removed > 0 {
+            self.persist()?;
+        }
+        Ok(removed)
+    }
+
+    /// Write to a temp file and rename so a crash never leaves a truncated index
+    fn persist(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let events: Vec<&ProcessedEvent> = self.events.values().collect();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&events)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::Address;
+
+    fn metadata(block_number: u64, tx: u8, log_index: u64) -> EventMetadata {
+        EventMetadata {
+            block_number,
+            tx_hash: Some(B256::repeat_byte(tx)),
+            log_index,
+            caller: Address::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_index_forgets_reorgs_and_prunes() {
+        let block_a = Some(B256::repeat_byte(0xaa));
+        let block_b = Some(B256::repeat_byte(0xbb));
+        let path = std::env::temp_dir()
+            .join(format!("coprocessor-dedup-{}", std::process::id()))
+            .join("processed.json");
+        let _ = fs::remove_file(&path);
+
+        let mut index = ProcessedEvents::load(&path).unwrap();
+        index.mark_processed(&metadata(5, 1, 0), block_a).unwrap();
+        index.mark_processed(&metadata(6, 2, 3), block_b).unwrap();
+        assert!(index.is_processed(&metadata(5, 1, 0)));
+        assert!(!index.is_processed(&metadata(5, 1, 1)));
+
+        // A restart finds the same entries
+        let mut index = ProcessedEvents::load(&path).unwrap();
+        assert!(index.is_processed(&metadata(6, 2, 3)));
+
+        // Reorged block is forgotten
+        assert_eq!(index.forget_block(block_b).unwrap(), 1);
+        assert!(!index.is_processed(&metadata(6, 2, 3)));
+
+        // A log from below the finalized block that was never processed is not a duplicate
+        index.mark_processed(&metadata(6, 2, 3), block_b).unwrap();
+        assert_eq!(index.prune_below(6).unwrap(), 1);
+        assert!(!index.is_processed(&metadata(4, 9, 0)));
+        let index = ProcessedEvents::load(&path).unwrap();
+        assert!(!index.is_processed(&metadata(5, 1, 0)));
+        assert!(index.is_processed(&metadata(6, 2, 3)));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,19 +1,38 @@
 //! FHE Event Listener
 use crate::config::Config;
 use crate::events::parser;
+use crate::events::signatures::{ALLOWED_FOR_DECRYPTION, ALLOWED_FOR_DECRYPTION_NO_CALLER};
+use crate::events::types::DecryptionRequest;
+use crate::finality;
+use crate::oracle::PendingDecryptions;
+use crate::policy::{Admission, Decision};
+use crate::processor::Processor;
+use crate::state::SharedState;
 use alloy::providers::{Provider, ProviderBuilder, WsConnect};
 use alloy::rpc::types::Filter;
 use anyhow::{Context, Result};
 use futures::StreamExt;
+use tokio::sync::mpsc::UnboundedSender;
 
 /// Start listening for FHE events from the TFHE Executor contract
 ///
 /// This function:
 /// 1. Connects to the blockchain via WebSocket
 /// 2. Sets up a filter for events from the TFHE Executor address
-/// 3. Subscribes to new logs matching the filter
-/// 4. Logs each event as it arrives
-pub async fn listen_to_events(config: &Config) -> Result<()> {
+/// 3. Subscribes to new logs matching the filter and to new block headers
+/// 4. Admits each event through the caller policy and queues it by priority,
+///    a worker processes queued ops speculatively. Ops over their caller's budget
+///    are queued on a later head instead.
+/// 5. Finalizes (or discards) results once their block is `confirmation_depth` deep
+/// 6. Forwards ACL AllowedForDecryption requests to the decryption oracle (if enabled)
+///    once their block is `confirmation_depth` deep and still canonical
+///
+/// Duplicate logs (reconnects, replays) are filtered by the processed event index.
+pub async fn listen_to_events(
+    config: &Config,
+    state: SharedState,
+    decryption_requests: Option<UnboundedSender<DecryptionRequest>>,
+) -> Result<()> {
     println!(
         "[Listener] Connecting to WebSocket at {}...",
         config.websocket_url
@@ -31,6 +50,10 @@ pub async fn listen_to_events(config: &Config) -> Result<()> {
         config.tfhe_executor_address
     );
     println!("[Listener] ACL address: {:?}", config.acl_address);
+    println!(
+        "[Listener] Confirmation depth: {}",
+        config.confirmation_depth
+    );
 
     // Filter for events from the TFHE Executor contract
     let filter = Filter::new().address(config.tfhe_executor_address);
@@ -40,15 +63,138 @@ pub async fn listen_to_events(config: &Config) -> Result<()> {
         .subscribe_logs(&filter)
         .await
         .context("Failed to subscribe to logs")?;
+    // Public decryption requests come from the ACL
+    let acl_filter = Filter::new()
+        .address(config.acl_address)
+        .event_signature(vec![*ALLOWED_FOR_DECRYPTION, *ALLOWED_FOR_DECRYPTION_NO_CALLER]);
+    let acl_sub = provider
+        .subscribe_logs(&acl_filter)
+        .await
+        .context("Failed to subscribe to ACL logs")?;
+    // New heads drive finalization and index pruning
+    let heads = provider
+        .subscribe_blocks()
+        .await
+        .context("Failed to subscribe to new blocks")?;
 
-    // Convert subscription to stream and process events
+    // Convert subscriptions to streams and process events
     let mut stream = sub.into_stream();
+    let mut heads = heads.into_stream();
+    let mut acl_stream = acl_sub.into_stream();
+    // With a KMS configured, inputs are only accepted once their proof verifies
+    let processor = Processor::new(state.clone(), config.confirmation_depth);
+    let mut admission = Admission::new(config.caller_policy.clone());
+    let mut pending_decryptions = PendingDecryptions::default();
+    tokio::spawn(processor.clone().run());
     println!("[Listener] Waiting for FHE events...");
     println!();
 
-    // Forwarding each log to the parser
-    while let Some(log) = stream.next().await {
-        parser::log_executor_event(&log);
+    loop {
+        tokio::select! {
+            log = stream.next() => {
+                let Some(log) = log else { break };
+                // Logs re-sent with `removed` belong to a block that was reorged out
+                if log.removed {
+                    processor.revert_block(log.block_hash).await?;
+                    admission.forget_block(log.block_hash);
+                    continue;
+                }
+                match parser::parse_fhe_event(&log) {
+                    Some(op) => {
+                        parser::log_fhe_operation(&op);
+                        let Some(metadata) = op.metadata() else { continue };
+                        // Don't spend a caller's budget on replays
+                        if state.processed.read().await.is_processed(metadata) {
+                            continue;
+                        }
+                        match admission.admit(metadata) {
+                            Decision::Accept(priority) => {
+                                state.queue.push(priority, op, log.block_hash).await;
+                            }
+                            Decision::Defer => {
+                                println!(
+                                    "[Listener] deferred {} from caller {}: per-block budget exhausted",
+                                    op.name(),
+                                    metadata.caller
+                                );
+                                admission.defer(op, log.block_hash);
+                            }
+                            Decision::Reject(reason) => println!(
+                                "[Listener] rejected {} from caller {}: {}",
+                                op.name(),
+                                metadata.caller,
+                                reason
+                            ),
+                        }
+                    }
+                    None => println!("[Parser] Failed to parse event from {:?}", log.address()),
+                }
+            }
+            log = acl_stream.next() => {
+                let Some(log) = log else { break };
+                if log.removed {
+                    let dropped = pending_decryptions.drop_block(log.block_hash);
+                    if dropped > 0 {
+                        println!("[Listener] dropped {} decryption request(s) of a reorged block", dropped);
+                    }
+                    continue;
+                }
+                let Some(request) = parser::parse_decryption_request(&log) else {
+                    println!("[Parser] Failed to parse ACL event from {:?}", log.address());
+                    continue;
+                };
+                let Some(sender) = &decryption_requests else {
+                    println!(
+                        "[Listener] AllowedForDecryption for {} handle(s) ignored: oracle disabled",
+                        request.handles.len()
+                    );
+                    continue;
+                };
+                // Marked processed by the oracle once fulfilled
+                if state.processed.read().await.is_processed(&request.metadata) {
+                    continue;
+                }
+                if config.confirmation_depth > 0 {
+                    pending_decryptions.hold(request);
+                } else if sender.send(request).is_err() {
+                    println!("[Listener] decryption oracle stopped");
+                }
+            }
+            head = heads.next() => {
+                let Some(head) = head else { break };
+                for (priority, op, block_hash) in admission.release(head.number) {
+                    state.queue.push(priority, op, block_hash).await;
+                }
+                if let Some(sender) = &decryption_requests {
+                    for request in pending_decryptions.take_confirmed(head.number, config.confirmation_depth) {
+                        match finality::is_canonical(&provider, request.metadata.block_number, request.block_hash).await {
+                            Ok(true) => {
+                                if sender.send(request).is_err() {
+                                    println!("[Listener] decryption oracle stopped");
+                                }
+                            }
+                            Ok(false) => println!(
+                                "[Listener] decryption request tx={:?} dropped: block {} not canonical",
+                                request.metadata.tx_hash, request.metadata.block_number
+                            ),
+                            // Checked again on the next head
+                            Err(e) => {
+                                println!(
+                                    "[Listener] canonical check for block {} failed: {}",
+                                    request.metadata.block_number, e
+                                );
+                                pending_decryptions.hold(request);
+                            }
+                        }
+                    }
+                }
+                if let Err(e) =
+                    finality::on_new_head(&state, &provider, head.number, config.confirmation_depth).await
+                {
+                    println!("[Listener] finality check failed at block {}: {}", head.number, e);
+                }
+            }
+        }
     }
 
     println!("[Listener] Event stream ended unexpectedly");
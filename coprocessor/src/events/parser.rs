@@ -281,6 +281,47 @@ fn parse_fhe_rand_bounded(metadata: EventMetadata, data: &[u8]) -> Option<FheOpe
     }))
 }
 
+/// Parse an ACL AllowedForDecryption log into a decryption request
+/// Layout: handlesList offset (32) + length (32) + handles (32 each)
+pub fn parse_decryption_request(log: &Log) -> Option<DecryptionRequest> {
+    let topics = log.topics();
+    let topic0 = topics.first()?;
+    let caller = if *topic0 == *ALLOWED_FOR_DECRYPTION {
+        Address::from_slice(&topics.get(1)?.as_slice()[12..])
+    } else if *topic0 == *ALLOWED_FOR_DECRYPTION_NO_CALLER {
+        Address::ZERO
+    } else {
+        return None;
+    };
+
+    let data = &log.data().data;
+    if data.len() < 64 {
+        return None;
+    }
+    let offset = U256::from_be_slice(&data[0..32]).saturating_to::<usize>();
+    let start = offset.checked_add(32).filter(|start| *start <= data.len())?;
+    let len = U256::from_be_slice(&data[offset..start]).saturating_to::<usize>();
+    let end = len
+        .checked_mul(32)
+        .and_then(|size| size.checked_add(start))
+        .filter(|end| *end <= data.len())?;
+    let handles = data[start..end]
+        .chunks_exact(32)
+        .map(B256::from_slice)
+        .collect();
+
+    Some(DecryptionRequest {
+        metadata: EventMetadata {
+            block_number: log.block_number.unwrap_or(0),
+            tx_hash: log.transaction_hash,
+            log_index: log.log_index.unwrap_or(0),
+            caller,
+        },
+        handles,
+        block_hash: log.block_hash,
+    })
+}
+
 /// Log a parsed FHE operation in a human-readable format
 pub fn log_fhe_operation(op: &FheOperation) {
     match op {
@@ -402,13 +443,3 @@ fn short_tx(tx: Option<B256>) -> String {
         None => "N/A".to_string(),
     }
 }
-
-/// Legacy function for backward compatibility
-pub fn log_executor_event(log: &Log) {
-    match parse_fhe_event(log) {
-        Some(op) => log_fhe_operation(&op),
-        None => {
-            println!("[Parser] Failed to parse event from {:?}", log.address());
-        }
-    }
-}
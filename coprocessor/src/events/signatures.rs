@@ -65,6 +65,13 @@ pub static FHE_RAND: Lazy<B256> =
 pub static FHE_RAND_BOUNDED: Lazy<B256> =
     Lazy::new(|| event_sig("FheRandBounded(address,uint256,uint8,bytes16,bytes32)"));
 
+// ACL events: AllowedForDecryption(address indexed caller, bytes32[] handlesList)
+// MockACL emits the older variant without the caller
+pub static ALLOWED_FOR_DECRYPTION: Lazy<B256> =
+    Lazy::new(|| event_sig("AllowedForDecryption(address,bytes32[])"));
+pub static ALLOWED_FOR_DECRYPTION_NO_CALLER: Lazy<B256> =
+    Lazy::new(|| event_sig("AllowedForDecryption(bytes32[])"));
+
 /// Check if a topic0 matches any known FHE event
 pub fn is_known_fhe_event(topic0: &B256) -> bool {
     *topic0 == *FHE_ADD
@@ -3,10 +3,11 @@
 //! These match the events defined in Zama's FHEEvents.sol contract.
 
 use alloy::primitives::{Address, B256, U256};
+use serde::{Deserialize, Serialize};
 
 pub type Handle = B256;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum FheType {
     Bool = 0,
@@ -60,6 +61,12 @@ impl FheType {
     }
 }
 
+/// Read the FheType embedded in a handle
+/// Handles follow the host layout: hash (21 bytes) | index (1) | chain id (8) | FheType (1) | version (1)
+pub fn handle_type(handle: &Handle) -> Option<FheType> {
+    FheType::from_u8(handle[30])
+}
+
 #[derive(Debug, Clone)]
 pub struct EventMetadata {
     pub block_number: u64,
@@ -222,6 +229,16 @@ pub struct FheRandBounded {
     pub result: Handle,
 }
 
+/// Public decryption request from the ACL
+/// Event: AllowedForDecryption(address indexed caller, bytes32[] handlesList)
+#[derive(Debug, Clone)]
+pub struct DecryptionRequest {
+    pub metadata: EventMetadata,
+    pub handles: Vec<Handle>,
+    /// Block the request was seen in, it counts as processed once fulfilled
+    pub block_hash: Option<B256>,
+}
+
 /// 
 /// 
 /// 
@@ -270,6 +287,34 @@ impl FheOperation {
         }
     }
 
+    /// Handles of the ciphertexts the operation reads, in operand order
+    /// (a scalar right hand side is a plaintext, not a handle)
+    pub fn input_handles(&self) -> Vec<Handle> {
+        match self {
+            FheOperation::Binary(op) if op.scalar_byte == 1 => vec![op.lhs],
+            FheOperation::Binary(op) => vec![op.lhs, op.rhs],
+            FheOperation::Unary(op) => vec![op.ct],
+            FheOperation::Cast(op) => vec![op.ct],
+            FheOperation::IfThenElse(op) => vec![op.control, op.if_true, op.if_false],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get the event metadata (block, tx hash, log index, caller)
+    pub fn metadata(&self) -> Option<&EventMetadata> {
+        match self {
+            FheOperation::Binary(op) => Some(&op.metadata),
+            FheOperation::Unary(op) => Some(&op.metadata),
+            FheOperation::TrivialEncrypt(op) => Some(&op.metadata),
+            FheOperation::Cast(op) => Some(&op.metadata),
+            FheOperation::IfThenElse(op) => Some(&op.metadata),
+            FheOperation::VerifyInput(op) => Some(&op.metadata),
+            FheOperation::Rand(op) => Some(&op.metadata),
+            FheOperation::RandBounded(op) => Some(&op.metadata),
+            FheOperation::Unknown { .. } => None,
+        }
+    }
+
     /// Get the caller address
     pub fn caller(&self) -> Option<Address> {
         match self {
@@ -0,0 +1,326 @@
+//! FHE Executor
+//! Computes the ciphertext of every operation under the server key of the KMS's active
+//! keyset. Operands are read from the result store by handle, so an op can only run once
+//! the ops producing its inputs did. Scalar operands are trivially encrypted first.
+use crate::types::{BinaryOp, BinaryOpType, FheOperation, FheType, Handle, UnaryOpType};
+use alloy::primitives::U256;
+use anyhow::{anyhow, bail, Result};
+use tfhe::integer::U256 as FheU256;
+use tfhe::prelude::*;
+use tfhe::{
+    FheBool, FheUint128, FheUint16, FheUint160, FheUint256, FheUint32, FheUint4, FheUint64,
+    FheUint8, ProvenCompactCiphertextList, Seed,
+};
+
+/// A deserialized ciphertext of one of the types the executor supports
+pub enum Value {
+    Bool(FheBool),
+    Uint4(FheUint4),
+    Uint8(FheUint8),
+    Uint16(FheUint16),
+    Uint32(FheUint32),
+    Uint64(FheUint64),
+    Uint128(FheUint128),
+    Uint160(FheUint160),
+    Uint256(FheUint256),
+}
+
+macro_rules! value_from {
+    ($($variant:ident($ty:ty)),*) => {
+        $(impl From<$ty> for Value {
+            fn from(ct: $ty) -> Self {
+                Value::$variant(ct)
+            }
+        })*
+    };
+}
+
+value_from!(
+    Bool(FheBool),
+    Uint4(FheUint4),
+    Uint8(FheUint8),
+    Uint16(FheUint16),
+    Uint32(FheUint32),
+    Uint64(FheUint64),
+    Uint128(FheUint128),
+    Uint160(FheUint160),
+    Uint256(FheUint256)
+);
+
+/// Run `$body` with `$ct` bound to the integer ciphertext inside `$value`
+macro_rules! with_uint {
+    ($value:expr, |$ct:ident| $body:expr) => {
+        match $value {
+            Value::Uint4($ct) => $body,
+            Value::Uint8($ct) => $body,
+            Value::Uint16($ct) => $body,
+            Value::Uint32($ct) => $body,
+            Value::Uint64($ct) => $body,
+            Value::Uint128($ct) => $body,
+            Value::Uint160($ct) => $body,
+            Value::Uint256($ct) => $body,
+            Value::Bool(_) => bail!("operation is not defined on ebool"),
+        }
+    };
+}
+
+/// Run `$body` with `$a` and `$b` bound to two integer ciphertexts of the same type
+macro_rules! with_uint_pair {
+    ($lhs:expr, $rhs:expr, |$a:ident, $b:ident| $body:expr) => {
+        match ($lhs, $rhs) {
+            (Value::Uint4($a), Value::Uint4($b)) => $body,
+            (Value::Uint8($a), Value::Uint8($b)) => $body,
+            (Value::Uint16($a), Value::Uint16($b)) => $body,
+            (Value::Uint32($a), Value::Uint32($b)) => $body,
+            (Value::Uint64($a), Value::Uint64($b)) => $body,
+            (Value::Uint128($a), Value::Uint128($b)) => $body,
+            (Value::Uint160($a), Value::Uint160($b)) => $body,
+            (Value::Uint256($a), Value::Uint256($b)) => $body,
+            _ => bail!("operands have different or unsupported types"),
+        }
+    };
+}
+
+/// Build a `Value` of `$fhe_type` from `$make`, called with the matching tfhe type
+macro_rules! of_type {
+    ($fhe_type:expr, $uint:ident => $make:expr, bool => $make_bool:expr) => {
+        match $fhe_type {
+            FheType::Bool => Value::Bool($make_bool),
+            FheType::Uint4 => { type $uint = FheUint4; Value::Uint4($make) }
+            FheType::Uint8 => { type $uint = FheUint8; Value::Uint8($make) }
+            FheType::Uint16 => { type $uint = FheUint16; Value::Uint16($make) }
+            FheType::Uint32 => { type $uint = FheUint32; Value::Uint32($make) }
+            FheType::Uint64 => { type $uint = FheUint64; Value::Uint64($make) }
+            FheType::Uint128 => { type $uint = FheUint128; Value::Uint128($make) }
+            FheType::Uint160 => { type $uint = FheUint160; Value::Uint160($make) }
+            FheType::Uint256 => { type $uint = FheUint256; Value::Uint256($make) }
+            other => bail!("{} is not supported", other.name()),
+        }
+    };
+}
+
+impl Value {
+    pub fn fhe_type(&self) -> FheType {
+        match self {
+            Value::Bool(_) => FheType::Bool,
+            Value::Uint4(_) => FheType::Uint4,
+            Value::Uint8(_) => FheType::Uint8,
+            Value::Uint16(_) => FheType::Uint16,
+            Value::Uint32(_) => FheType::Uint32,
+            Value::Uint64(_) => FheType::Uint64,
+            Value::Uint128(_) => FheType::Uint128,
+            Value::Uint160(_) => FheType::Uint160,
+            Value::Uint256(_) => FheType::Uint256,
+        }
+    }
+
+    /// Bincode serialization of the ciphertext, what the store and the KMS work with
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        Ok(match self {
+            Value::Bool(ct) => bincode::serialize(ct)?,
+            Value::Uint4(ct) => bincode::serialize(ct)?,
+            Value::Uint8(ct) => bincode::serialize(ct)?,
+            Value::Uint16(ct) => bincode::serialize(ct)?,
+            Value::Uint32(ct) => bincode::serialize(ct)?,
+            Value::Uint64(ct) => bincode::serialize(ct)?,
+            Value::Uint128(ct) => bincode::serialize(ct)?,
+            Value::Uint160(ct) => bincode::serialize(ct)?,
+            Value::Uint256(ct) => bincode::serialize(ct)?,
+        })
+    }
+
+    pub fn deserialize(fhe_type: FheType, bytes: &[u8]) -> Result<Self> {
+        Ok(of_type!(fhe_type, T => bincode::deserialize::<T>(bytes)?, bool => bincode::deserialize(bytes)?))
+    }
+
+    /// Trivial (public) encryption of `value`, truncated to the width of `fhe_type`
+    fn trivial(fhe_type: FheType, value: U256) -> Result<Self> {
+        let value = FheU256::from(to_words(value));
+        Ok(of_type!(fhe_type, T => T::try_encrypt_trivial(value)?, bool => FheBool::try_encrypt_trivial(value != FheU256::ZERO)?))
+    }
+
+    fn cast(self, to: FheType) -> Result<Self> {
+        Ok(match self {
+            Value::Bool(ct) => of_type!(to, T => T::cast_from(ct), bool => ct),
+            value => with_uint!(value, |ct| of_type!(to, T => T::cast_from(ct), bool => ct.ne(0u8))),
+        })
+    }
+}
+
+/// Compute the result of `op` from the ciphertexts of `op.input_handles()`, in that order
+/// The server key has to be set on the calling thread (tfhe::set_server_key).
+pub fn execute(op: &FheOperation, inputs: Vec<Value>) -> Result<Value> {
+    let mut inputs = inputs.into_iter();
+    let mut next = || inputs.next().ok_or_else(|| anyhow!("missing operand"));
+    match op {
+        FheOperation::Binary(bin) => {
+            let lhs = next()?;
+            let rhs = if bin.scalar_byte == 1 {
+                Value::trivial(lhs.fhe_type(), U256::from_be_bytes(bin.rhs.0))?
+            } else {
+                next()?
+            };
+            binary(bin, lhs, rhs)
+        }
+        FheOperation::Unary(un) => match (un.op_type, next()?) {
+            (UnaryOpType::Not, Value::Bool(ct)) => Ok(Value::Bool(!ct)),
+            (UnaryOpType::Not, value) => with_uint!(value, |ct| Ok((!ct).into())),
+            (UnaryOpType::Neg, value) => with_uint!(value, |ct| Ok((-ct).into())),
+        },
+        FheOperation::TrivialEncrypt(enc) => Value::trivial(enc.to_type, enc.plaintext),
+        FheOperation::Cast(cast) => next()?.cast(cast.to_type),
+        FheOperation::IfThenElse(_) => {
+            let Value::Bool(control) = next()? else {
+                bail!("if-then-else control is not an ebool");
+            };
+            match (next()?, next()?) {
+                (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(control.if_then_else(&a, &b))),
+                (a, b) => with_uint_pair!(a, b, |a, b| Ok(control.if_then_else(&a, &b).into())),
+            }
+        }
+        FheOperation::VerifyInput(input) => {
+            // The proof was checked before the op got here
+            let list: ProvenCompactCiphertextList = bincode::deserialize(&input.input_proof)?;
+            let expanded = list.expand_without_verification()?;
+            let index = input_index(&input.input_handle);
+            let missing = || anyhow!("input list has no entry {}", index);
+            Ok(of_type!(input.input_type, T => expanded.get::<T>(index)?.ok_or_else(missing)?, bool => expanded.get::<FheBool>(index)?.ok_or_else(missing)?))
+        }
+        FheOperation::Rand(rand) => random(rand.rand_type, rand.seed, None),
+        FheOperation::RandBounded(rand) => random(rand.rand_type, rand.seed, Some(rand.upper_bound)),
+        FheOperation::Unknown { .. } => bail!("unknown operation"),
+    }
+}
+
+fn binary(bin: &BinaryOp, lhs: Value, rhs: Value) -> Result<Value> {
+    use BinaryOpType::*;
+    if let (Value::Bool(a), Value::Bool(b)) = (&lhs, &rhs) {
+        return Ok(Value::Bool(match bin.op_type {
+            BitAnd => a & b,
+            BitOr => a | b,
+            BitXor => a ^ b,
+            Eq => a.eq(b),
+            Ne => a.ne(b),
+            other => bail!("{} is not defined on ebool", other.name()),
+        }));
+    }
+    with_uint_pair!(lhs, rhs, |a, b| Ok(match bin.op_type {
+        Add => (&a + &b).into(),
+        Sub => (&a - &b).into(),
+        Mul => (&a * &b).into(),
+        Div => (&a / &b).into(),
+        Rem => (&a % &b).into(),
+        BitAnd => (&a & &b).into(),
+        BitOr => (&a | &b).into(),
+        BitXor => (&a ^ &b).into(),
+        Shl => (&a << &b).into(),
+        Shr => (&a >> &b).into(),
+        Rotl => a.rotate_left(&b).into(),
+        Rotr => a.rotate_right(&b).into(),
+        Eq => a.eq(&b).into(),
+        Ne => a.ne(&b).into(),
+        Ge => a.ge(&b).into(),
+        Gt => a.gt(&b).into(),
+        Le => a.le(&b).into(),
+        Lt => a.lt(&b).into(),
+        Min => a.min(&b).into(),
+        Max => a.max(&b).into(),
+    }))
+}
+
+/// Oblivious pseudo random value, below `upper_bound` when given (a power of two)
+fn random(fhe_type: FheType, seed: [u8; 16], upper_bound: Option<U256>) -> Result<Value> {
+    let seed = Seed(u128::from_be_bytes(seed));
+    let Some(bound) = upper_bound else {
+        return Ok(of_type!(fhe_type, T => T::generate_oblivious_pseudo_random(seed), bool => FheBool::generate_oblivious_pseudo_random(seed)));
+    };
+    if !bound.is_power_of_two() {
+        bail!("random upper bound {} is not a power of two", bound);
+    }
+    let bits = bound.trailing_zeros() as u64;
+    // The host contract has no bounded ebool random, an ebool is one random bit anyway
+    Ok(of_type!(fhe_type, T => T::generate_oblivious_pseudo_random_bounded(seed, bits), bool => FheBool::generate_oblivious_pseudo_random(seed)))
+}
+
+/// Position of an input in its proven list, byte 21 of the input handle
+/// (hash (21 bytes) | index (1) | chain id (8) | FheType (1) | version (1))
+pub fn input_index(handle: &Handle) -> usize {
+    handle[21] as usize
+}
+
+/// alloy U256 as the (low, high) u128 words tfhe's U256 is built from
+fn to_words(value: U256) -> (u128, u128) {
+    let limbs = value.as_limbs();
+    let low = limbs[0] as u128 | (limbs[1] as u128) << 64;
+    let high = limbs[2] as u128 | (limbs[3] as u128) << 64;
+    (low, high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::types::{Cast, EventMetadata, TrivialEncrypt};
+    use alloy::primitives::{Address, B256};
+    use tfhe::shortint::parameters::PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64;
+    use tfhe::{generate_keys, ConfigBuilder};
+
+    fn metadata() -> EventMetadata {
+        EventMetadata {
+            block_number: 1,
+            tx_hash: None,
+            log_index: 0,
+            caller: Address::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_execute_chained_ops() {
+        let config = ConfigBuilder::with_custom_parameters(PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64).build();
+        let (client_key, server_key) = generate_keys(config);
+        tfhe::set_server_key(server_key);
+
+        let seven = execute(
+            &FheOperation::TrivialEncrypt(TrivialEncrypt {
+                metadata: metadata(),
+                plaintext: U256::from(7),
+                to_type: FheType::Uint8,
+                result: B256::repeat_byte(1),
+            }),
+            Vec::new(),
+        )
+        .unwrap();
+        // Stored and read back the way the processor hands operands over
+        let seven = Value::deserialize(seven.fhe_type(), &seven.serialize().unwrap()).unwrap();
+
+        let mut rhs = [0u8; 32];
+        rhs[31] = 250;
+        let sum = execute(
+            &FheOperation::Binary(BinaryOp {
+                metadata: metadata(),
+                op_type: BinaryOpType::Add,
+                lhs: B256::repeat_byte(1),
+                rhs: B256::from(rhs),
+                scalar_byte: 1,
+                result: B256::repeat_byte(2),
+            }),
+            vec![seven],
+        )
+        .unwrap();
+        let Value::Uint8(ct) = &sum else { panic!("sum is not an euint8") };
+        let wrapped: u8 = ct.decrypt(&client_key);
+        assert_eq!(wrapped, 1);
+
+        let nonzero = execute(
+            &FheOperation::Cast(Cast {
+                metadata: metadata(),
+                ct: B256::repeat_byte(2),
+                to_type: FheType::Bool,
+                result: B256::repeat_byte(3),
+            }),
+            vec![sum],
+        )
+        .unwrap();
+        let Value::Bool(ct) = nonzero else { panic!("cast result is not an ebool") };
+        assert!(ct.decrypt(&client_key));
+    }
+}
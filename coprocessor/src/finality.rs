@@ -0,0 +1,71 @@
+//! Confirmation Depth Gating
+//! Promotes speculative results to final once their block is `depth` blocks deep,
+//! and throws them away if the block is no longer part of the canonical chain.
+use crate::state::SharedState;
+use alloy::eips::BlockNumberOrTag;
+use alloy::primitives::B256;
+use alloy::providers::Provider;
+use anyhow::Result;
+
+/// Finalized blocks whose per-tx HCU usage stays queryable through the status API
+const HCU_TX_RETENTION_BLOCKS: u64 = 1024;
+
+/// Handle a new chain head
+///
+/// A result from block `n` becomes final once the head reaches `n + depth`. Before
+/// finalizing, the stored block hash is compared against the canonical hash at that
+/// height so results from orphaned blocks are discarded instead. The processed event
+/// index is then pruned below the finalized block.
+pub async fn on_new_head<P: Provider>(
+    state: &SharedState,
+    provider: &P,
+    head: u64,
+    depth: u64,
+) -> Result<()> {
+    let Some(cutoff) = head.checked_sub(depth) else {
+        return Ok(());
+    };
+
+    let pending = state.store.read().await.speculative_blocks(cutoff);
+    for (number, hash) in pending {
+        let canonical = is_canonical(provider, number, hash).await?;
+
+        let mut store = state.store.write().await;
+        if canonical {
+            let count = store.finalize_block(hash)?;
+            println!(
+                "[Finality] block {} confirmed at head {}: {} result(s) final",
+                number, head, count
+            );
+        } else {
+            let count = store.discard_block(hash)?;
+            drop(store);
+            state.processed.write().await.forget_block(hash)?;
+            state.hcu.write().await.forget_block(hash);
+            println!(
+                "[Finality] block {} ({:?}) not canonical: discarded {} result(s)",
+                number, hash, count
+            );
+        }
+    }
+
+    state.processed.write().await.prune_below(cutoff)?;
+    state
+        .hcu
+        .write()
+        .await
+        .prune_below(cutoff.saturating_sub(HCU_TX_RETENTION_BLOCKS));
+    Ok(())
+}
+
+/// Whether `hash` is the canonical block at height `number`
+pub async fn is_canonical<P: Provider>(provider: &P, number: u64, hash: Option<B256>) -> Result<bool> {
+    if hash.is_none() {
+        return Ok(false);
+    }
+    let canonical = provider
+        .get_block_by_number(BlockNumberOrTag::Number(number))
+        .await?
+        .map(|block| block.header.hash);
+    Ok(canonical == hash)
+}
@@ -0,0 +1,285 @@
+//! Homomorphic Complexity Units (HCU)
+//! Cost table for every FHE operation, indexed by operand FheType and scalar-ness,
+//! plus per-transaction / per-caller accounting. The numbers approximate the
+//! HCULimit host contract so totals can be reconciled with what the chain charges.
+
+use crate::types::{handle_type, BinaryOpType, FheOperation, FheType, UnaryOpType};
+use alloy::primitives::{Address, B256};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Per-transaction limit enforced by HCULimit (MAX_HOMOMORPHIC_COMPUTE_UNITS_PER_TX)
+pub const DEFAULT_TX_HCU_LIMIT: u64 = 20_000_000;
+
+/// Callers whose totals are kept, past that the caller with the smallest total is evicted
+const MAX_TRACKED_CALLERS: usize = 10_000;
+
+/// Costs per operand width: [bool, 4, 8, 16, 32, 64, 128, 160, 256] bits.
+/// `ebytesN` types are priced like 256-bit operands.
+type CostRow = [u64; 9];
+
+fn by_type(fhe_type: FheType, row: CostRow) -> u64 {
+    let index = match fhe_type {
+        FheType::Bool => 0,
+        FheType::Uint4 => 1,
+        FheType::Uint8 => 2,
+        FheType::Uint16 => 3,
+        FheType::Uint32 => 4,
+        FheType::Uint64 => 5,
+        FheType::Uint128 => 6,
+        FheType::Uint160 => 7,
+        FheType::Uint256 | FheType::Bytes64 | FheType::Bytes128 | FheType::Bytes256 => 8,
+    };
+    row[index]
+}
+
+/// Cost of a binary operation on `fhe_type` operands
+pub fn binary_cost(op_type: BinaryOpType, fhe_type: FheType, scalar: bool) -> u64 {
+    use BinaryOpType::*;
+    let row: CostRow = match (op_type, scalar) {
+        (Add | Sub, true) => [0, 65_000, 84_000, 93_000, 95_000, 133_000, 172_000, 172_000, 259_000],
+        (Add | Sub, false) => [0, 65_000, 87_000, 93_000, 125_000, 162_000, 259_000, 259_000, 344_000],
+        (Mul, true) => [0, 88_000, 122_000, 193_000, 265_000, 365_000, 696_000, 696_000, 1_075_000],
+        (Mul, false) => [0, 150_000, 150_000, 222_000, 328_000, 596_000, 1_686_000, 1_686_000, 3_008_000],
+        // Only scalar divisors are supported on chain
+        (Div, _) => [0, 139_000, 210_000, 302_000, 438_000, 715_000, 1_225_000, 1_225_000, 1_920_000],
+        (Rem, _) => [0, 286_000, 440_000, 580_000, 792_000, 1_153_000, 1_943_000, 1_943_000, 2_915_000],
+        (BitAnd | BitOr | BitXor, true) => [22_000, 31_000, 31_000, 31_000, 32_000, 34_000, 37_000, 37_000, 38_000],
+        (BitAnd | BitOr | BitXor, false) => [25_000, 31_000, 31_000, 31_000, 32_000, 34_000, 37_000, 37_000, 38_000],
+        (Shl | Shr | Rotl | Rotr, true) => [0, 32_000, 32_000, 32_000, 32_000, 34_000, 37_000, 37_000, 39_000],
+        (Shl | Shr | Rotl | Rotr, false) => [0, 91_000, 92_000, 125_000, 162_000, 209_000, 276_000, 276_000, 397_000],
+        (Eq | Ne, true) => [25_000, 55_000, 55_000, 55_000, 82_000, 83_000, 117_000, 117_000, 118_000],
+        (Eq | Ne, false) => [26_000, 55_000, 55_000, 83_000, 86_000, 120_000, 122_000, 137_000, 152_000],
+        (Ge | Gt | Le | Lt, true) => [0, 52_000, 52_000, 58_000, 84_000, 88_000, 149_000, 149_000, 149_000],
+        (Ge | Gt | Le | Lt, false) => [0, 63_000, 63_000, 84_000, 118_000, 152_000, 210_000, 210_000, 210_000],
+        (Min | Max, true) => [0, 106_000, 114_000, 140_000, 154_000, 192_000, 325_000, 325_000, 325_000],
+        (Min | Max, false) => [0, 111_000, 119_000, 146_000, 182_000, 240_000, 330_000, 330_000, 330_000],
+    };
+    by_type(fhe_type, row)
+}
+
+pub fn unary_cost(op_type: UnaryOpType, fhe_type: FheType) -> u64 {
+    let row: CostRow = match op_type {
+        UnaryOpType::Neg => [0, 60_000, 79_000, 93_000, 131_000, 149_000, 219_000, 219_000, 310_000],
+        UnaryOpType::Not => [2, 4, 9, 16, 32, 63, 130, 130, 130],
+    };
+    by_type(fhe_type, row)
+}
+
+pub fn cast_cost(from_type: FheType) -> u64 {
+    by_type(from_type, [32; 9])
+}
+
+pub fn trivial_encrypt_cost(to_type: FheType) -> u64 {
+    by_type(to_type, [32, 32, 32, 32, 32, 32, 32, 32, 32])
+}
+
+pub fn if_then_else_cost(fhe_type: FheType) -> u64 {
+    by_type(
+        fhe_type,
+        [55_000, 55_000, 55_000, 55_000, 55_000, 55_000, 57_000, 83_000, 108_000],
+    )
+}
+
+pub fn rand_cost(rand_type: FheType, bounded: bool) -> u64 {
+    let row: CostRow = if bounded {
+        [0, 23_000, 23_000, 23_000, 24_000, 24_000, 25_000, 25_000, 30_000]
+    } else {
+        [19_000, 23_000, 23_000, 23_000, 24_000, 24_000, 25_000, 25_000, 30_000]
+    };
+    by_type(rand_type, row)
+}
+
+/// HCU charged for an operation
+///
+/// Operand types come from the type byte embedded in the input handles. If a handle
+/// doesn't carry a valid type, the op is priced as if it were on 64-bit operands.
+pub fn op_cost(op: &FheOperation) -> u64 {
+    let operand = |handle| handle_type(handle).unwrap_or(FheType::Uint64);
+    match op {
+        FheOperation::Binary(bin) => binary_cost(bin.op_type, operand(&bin.lhs), bin.scalar_byte == 1),
+        FheOperation::Unary(un) => unary_cost(un.op_type, operand(&un.ct)),
+        FheOperation::Cast(cast) => cast_cost(operand(&cast.ct)),
+        FheOperation::TrivialEncrypt(enc) => trivial_encrypt_cost(enc.to_type),
+        FheOperation::IfThenElse(ite) => if_then_else_cost(operand(&ite.if_true)),
+        FheOperation::Rand(r) => rand_cost(r.rand_type, false),
+        FheOperation::RandBounded(r) => rand_cost(r.rand_type, true),
+        // Input verification isn't metered by HCULimit
+        FheOperation::VerifyInput(_) | FheOperation::Unknown { .. } => 0,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TxUsage {
+    pub caller: Address,
+    pub block_number: u64,
+    #[serde(skip)]
+    pub block_hash: Option<B256>,
+    pub ops: u64,
+    pub hcu: u64,
+    pub over_budget: bool,
+}
+
+/// Cumulative HCU per transaction and per caller
+pub struct HcuTracker {
+    tx_limit: u64,
+    per_tx: HashMap<B256, TxUsage>,
+    per_caller: HashMap<Address, u64>,
+}
+
+impl HcuTracker {
+    pub fn new(tx_limit: u64) -> Self {
+        Self {
+            tx_limit,
+            per_tx: HashMap::new(),
+            per_caller: HashMap::new(),
+        }
+    }
+
+    /// Add the cost of `op` to its transaction and caller
+    /// Returns true the first time the transaction goes over the per-tx limit
+    pub fn record(&mut self, op: &FheOperation, block_hash: Option<B256>) -> bool {
+        let Some(metadata) = op.metadata() else {
+            return false;
+        };
+        let cost = op_cost(op);
+        if !self.per_caller.contains_key(&metadata.caller) && self.per_caller.len() >= MAX_TRACKED_CALLERS {
+            self.evict_smallest_caller();
+        }
+        *self.per_caller.entry(metadata.caller).or_insert(0) += cost;
+
+        let Some(tx_hash) = metadata.tx_hash else {
+            return false;
+        };
+        let usage = self.per_tx.entry(tx_hash).or_insert(TxUsage {
+            caller: metadata.caller,
+            block_number: metadata.block_number,
+            block_hash,
+            ops: 0,
+            hcu: 0,
+            over_budget: false,
+        });
+        usage.ops += 1;
+        usage.hcu += cost;
+        if usage.hcu > self.tx_limit && !usage.over_budget {
+            usage.over_budget = true;
+            return true;
+        }
+        false
+    }
+
+    /// Remove usage recorded for transactions in a reorged-out block
+    pub fn forget_block(&mut self, block_hash: Option<B256>) {
+        let per_caller = &mut self.per_caller;
+        self.per_tx.retain(|_, usage| {
+            if usage.block_hash != block_hash {
+                return true;
+            }
+            if let Some(total) = per_caller.get_mut(&usage.caller) {
+                *total = total.saturating_sub(usage.hcu);
+            }
+            false
+        });
+        self.per_caller.retain(|_, total| *total > 0);
+    }
+
+    fn evict_smallest_caller(&mut self) {
+        let smallest = self.per_caller.iter().min_by_key(|(_, total)| **total).map(|(caller, _)| *caller);
+        if let Some(caller) = smallest {
+            self.per_caller.remove(&caller);
+        }
+    }
+
+    /// Drop per-transaction history below `block` (caller totals are kept)
+    pub fn prune_below(&mut self, block: u64) {
+        self.per_tx.retain(|_, usage| usage.block_number >= block);
+    }
+
+    pub fn tx_limit(&self) -> u64 {
+        self.tx_limit
+    }
+
+    pub fn tx(&self, tx_hash: &B256) -> Option<&TxUsage> {
+        self.per_tx.get(tx_hash)
+    }
+
+    pub fn callers(&self) -> &HashMap<Address, u64> {
+        &self.per_caller
+    }
+
+    pub fn over_budget_txs(&self) -> Vec<B256> {
+        self.per_tx
+            .iter()
+            .filter(|(_, usage)| usage.over_budget)
+            .map(|(tx_hash, _)| *tx_hash)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::types::{BinaryOp, EventMetadata};
+
+    fn typed_handle(fhe_type: FheType, id: u8) -> B256 {
+        let mut handle = B256::repeat_byte(id);
+        handle.0[30] = fhe_type as u8;
+        handle
+    }
+
+    fn add(tx: u8, scalar: bool) -> FheOperation {
+        FheOperation::Binary(BinaryOp {
+            metadata: EventMetadata {
+                block_number: 1,
+                tx_hash: Some(B256::repeat_byte(tx)),
+                log_index: 0,
+                caller: Address::repeat_byte(7),
+            },
+            op_type: BinaryOpType::Add,
+            lhs: typed_handle(FheType::Uint64, 1),
+            rhs: typed_handle(FheType::Uint64, 2),
+            scalar_byte: scalar as u8,
+            result: typed_handle(FheType::Uint64, 3),
+        })
+    }
+
+    #[test]
+    fn test_cost_depends_on_type_and_scalar() {
+        assert_eq!(op_cost(&add(1, false)), 162_000);
+        assert_eq!(op_cost(&add(1, true)), 133_000);
+        assert!(binary_cost(BinaryOpType::Mul, FheType::Uint128, false) > binary_cost(BinaryOpType::Mul, FheType::Uint8, false));
+    }
+
+    #[test]
+    fn test_tx_budget_flag() {
+        let mut tracker = HcuTracker::new(300_000);
+        assert!(!tracker.record(&add(1, false), None));
+        assert!(tracker.record(&add(1, false), None));
+        // Only flagged once
+        assert!(!tracker.record(&add(1, false), None));
+
+        let usage = tracker.tx(&B256::repeat_byte(1)).unwrap();
+        assert_eq!(usage.ops, 3);
+        assert_eq!(usage.hcu, 3 * 162_000);
+        assert_eq!(tracker.callers()[&Address::repeat_byte(7)], 3 * 162_000);
+        assert_eq!(tracker.over_budget_txs(), vec![B256::repeat_byte(1)]);
+
+        tracker.forget_block(None);
+        assert!(tracker.callers().is_empty());
+    }
+
+    #[test]
+    fn test_caller_totals_are_capped() {
+        let mut tracker = HcuTracker::new(DEFAULT_TX_HCU_LIMIT);
+        for i in 0..=MAX_TRACKED_CALLERS {
+            let mut op = add(1, i % 2 == 0);
+            if let FheOperation::Binary(bin) = &mut op {
+                bin.metadata.caller = Address::left_padding_from(&(i as u64).to_be_bytes());
+            }
+            tracker.record(&op, None);
+        }
+        assert_eq!(tracker.callers().len(), MAX_TRACKED_CALLERS);
+        // A cheaper scalar caller made room, the last one is in
+        assert!(tracker.callers().contains_key(&Address::left_padding_from(&(MAX_TRACKED_CALLERS as u64).to_be_bytes())));
+    }
+}
@@ -0,0 +1,111 @@
+//! Input proof verification
+//! Client inputs are proven compact ciphertext lists. The proof is bound to the contract
+//! the input is for and the user submitting it, so a ciphertext can neither be malformed
+//! nor replayed through another contract or account.
+use crate::server_key::{KeyPins, KmsSource, LEGACY_TFHE_PARAMS};
+use alloy::primitives::Address;
+use anyhow::{anyhow, bail, Result};
+use common::fingerprint::check_fingerprint;
+use tfhe::safe_serialization::safe_deserialize;
+use tfhe::zk::{CompactPkePublicParams, ZkVerificationOutCome};
+use tfhe::{CompactPublicKey, ProvenCompactCiphertextList};
+
+/// Public key and CRS of one keyset, what input proofs are checked against
+pub struct InputVerifier {
+    public_key: CompactPublicKey,
+    /// None for keysets of the legacy preset, their Gaussian noise rules out a CRS
+    crs: Option<CompactPkePublicParams>,
+}
+
+/// Upper bound on the serialized CRS, the same the KMS writes it with
+const CRS_SIZE_LIMIT: u64 = 1 << 30;
+
+/// Data a proof is bound to: the contract, then the user (same layout as the client's)
+pub fn input_metadata(contract: Address, user: Address) -> Vec<u8> {
+    [contract.as_slice(), user.as_slice()].concat()
+}
+
+impl InputVerifier {
+    /// Fetch the public key and CRS of keyset `key_id` (of preset `params`) from the KMS,
+    /// refusing any that doesn't match its pin
+    /// Legacy keysets have no CRS, nothing proves inputs under them so all are refused.
+    pub async fn fetch(kms: &KmsSource, key_id: &str, params: &str, pins: &KeyPins) -> Result<Self> {
+        let public_key = kms.download(&format!("keys/{}/public/raw", key_id)).await?.bytes().await?;
+        if let Some(pinned) = &pins.public_key {
+            check_fingerprint("public key", &public_key, pinned)?;
+        }
+        let crs = if params == LEGACY_TFHE_PARAMS {
+            None
+        } else {
+            let crs = kms.download(&format!("keys/{}/crs/raw", key_id)).await?.bytes().await?;
+            if let Some(pinned) = &pins.crs {
+                check_fingerprint("CRS", &crs, pinned)?;
+            }
+            Some(safe_deserialize(crs.as_ref(), CRS_SIZE_LIMIT).map_err(|e| anyhow!("invalid CRS: {}", e))?)
+        };
+        Ok(Self {
+            public_key: bincode::deserialize(&public_key)?,
+            crs,
+        })
+    }
+
+    /// Check that `input_proof` is a proven list whose proof holds for `contract` and `user`
+    pub fn verify(&self, input_proof: &[u8], contract: Address, user: Address) -> Result<()> {
+        let Some(crs) = &self.crs else {
+            bail!("the keyset has no CRS to verify input proofs against, activate a newer keyset");
+        };
+        let list: ProvenCompactCiphertextList = bincode::deserialize(input_proof)?;
+        let metadata = input_metadata(contract, user);
+        match list.verify(crs, &self.public_key, &metadata) {
+            ZkVerificationOutCome::Valid => Ok(()),
+            ZkVerificationOutCome::Invalid => bail!("input proof does not verify"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tfhe::zk::{CompactPkeCrs, ZkComputeLoad};
+    use tfhe::shortint::parameters::compact_public_key_only::p_fail_2_minus_64::ks_pbs::PARAM_PKE_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64;
+    use tfhe::shortint::parameters::key_switching::p_fail_2_minus_64::ks_pbs::PARAM_KEYSWITCH_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64;
+    use tfhe::shortint::parameters::PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64;
+    use tfhe::{ClientKey, ConfigBuilder};
+
+    #[test]
+    fn test_verify_accepts_only_bound_inputs() {
+        // The dedicated compact key parameters keep the CRS small enough for a test
+        let config = ConfigBuilder::with_custom_parameters(PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64)
+            .use_dedicated_compact_public_key_parameters((
+                PARAM_PKE_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64,
+                PARAM_KEYSWITCH_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M64,
+            ))
+            .build();
+        let client_key = ClientKey::generate(config);
+        let verifier = InputVerifier {
+            public_key: CompactPublicKey::new(&client_key),
+            crs: Some(CompactPkeCrs::from_config(config, 8).unwrap().public_params().clone()),
+        };
+        let contract = Address::repeat_byte(0xc0);
+        let user = Address::repeat_byte(0x05);
+        let list = ProvenCompactCiphertextList::builder(&verifier.public_key)
+            .push(42u8)
+            .build_with_proof_packed(verifier.crs.as_ref().unwrap(), &input_metadata(contract, user), ZkComputeLoad::Proof)
+            .unwrap();
+        let proof = bincode::serialize(&list).unwrap();
+
+        assert!(verifier.verify(&proof, contract, user).is_ok());
+        // Replayed through another contract or by another user
+        assert!(verifier.verify(&proof, Address::repeat_byte(0xc1), user).is_err());
+        assert!(verifier.verify(&proof, contract, Address::repeat_byte(0x06)).is_err());
+        // Not a proven list at all
+        assert!(verifier.verify(b"not a ciphertext", contract, user).is_err());
+
+        // A legacy keyset without CRS proves nothing
+        let legacy = InputVerifier {
+            public_key: verifier.public_key,
+            crs: None,
+        };
+        assert!(legacy.verify(&proof, contract, user).is_err());
+    }
+}
@@ -1,5 +1,18 @@
 mod config;
+mod dedup;
 mod events;
+mod executor;
+mod finality;
+mod hcu;
+mod input_verifier;
+mod oracle;
+mod policy;
+mod processor;
+mod queue;
+mod server_key;
+mod state;
+mod status;
+mod store;
 mod types;
 
 use anyhow::Result;
@@ -11,10 +24,82 @@ async fn main() -> Result<()> {
     let config = config::load_config().expect("Failed to load config from .env");
 
     println!("   WebSocket URL:     {}", config.websocket_url);
+    println!("   Chain ID:          {}", config.chain_id);
     println!("   TFHE Executor:     {:?}", config.tfhe_executor_address);
     println!("   ACL Address:       {:?}", config.acl_address);
+    println!("   KMS Verifier:      {:?}", config.deployments.kms_verifier);
+    println!("   Input Verifier:    {:?}", config.deployments.input_verifier);
+    println!("   EncryptedERC20:    {:?}", config.deployments.encrypted_erc20);
+    println!("   Confirmations:     {}", config.confirmation_depth);
+    println!(
+        "   Caller Policy:     {:?} ({} routed, {} denied)",
+        config.caller_policy.mode,
+        config.caller_policy.callers.len(),
+        config.caller_policy.denied.len()
+    );
+    println!("   HCU tx limit:      {}", config.hcu_tx_limit);
+    println!("   Status API port:   {}", config.status_port);
+    println!("   Data directory:    {}", config.data_dir.display());
+    println!(
+        "   Ciphertext API:    {}",
+        if config.ciphertext_token.is_some() {
+            "KMS token required"
+        } else {
+            "disabled (COPROCESSOR_API_TOKEN not set)"
+        }
+    );
+    match &config.oracle {
+        Some(oracle) => println!(
+            "   Decryption oracle: KMS {} -> gateway {:?}",
+            oracle.kms_url, oracle.gateway_address
+        ),
+        None => println!("   Decryption oracle: disabled"),
+    }
+    println!(
+        "   KMS keys:          compressed server key, public key and CRS from {} ({}, {})",
+        config.kms_url,
+        config.tfhe_params,
+        config.key_pins.describe()
+    );
     println!();
 
-    events::listener::listen_to_events(&config).await?;
+    let state = state::CoprocessorState::open(&config.data_dir, config.hcu_tx_limit)?;
+    tokio::spawn(status::serve(
+        state.clone(),
+        config.status_port,
+        config.ciphertext_token.clone(),
+    ));
+    let kms = server_key::KmsSource {
+        url: config.kms_url.clone(),
+        token: config.kms_token.clone(),
+    };
+    let bootstrap = server_key::bootstrap(
+        state.clone(),
+        kms,
+        config.key_pins.clone(),
+        config.tfhe_params.clone(),
+    );
+    tokio::spawn(async move {
+        // Nothing is processed without the keys, and pinned keys that don't match stay wrong
+        if let Err(e) = bootstrap.await {
+            println!("[ServerKey] giving up: {:#}", e);
+            std::process::exit(1);
+        }
+    });
+
+    // Decryption requests flow from the listener to the oracle
+    let (decryption_tx, decryption_rx) = tokio::sync::mpsc::unbounded_channel();
+    let decryption_tx = match config.oracle.clone() {
+        Some(oracle_config) => {
+            let oracle =
+                oracle::DecryptionOracle::new(state.clone(), oracle_config, &config.websocket_url)
+                    .await?;
+            tokio::spawn(oracle.run(decryption_rx));
+            Some(decryption_tx)
+        }
+        None => None,
+    };
+
+    events::listener::listen_to_events(&config, state, decryption_tx).await?;
     Ok(())
 }
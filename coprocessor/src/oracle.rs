@@ -0,0 +1,302 @@
+//! Public Decryption Oracle
+//! Driven by the ACL's AllowedForDecryption events:
+//! 1. Collect the handles listed in the event
+//! 2. Wait until their computed ciphertexts are final in the result store
+//! 3. Ask the KMS to decrypt them (POST /decrypt), getting back a KMSVerifier decryption proof.
+//!    Only the handles are sent, the KMS fetches their ciphertexts from our /ciphertexts
+//! 4. Post the plaintexts and proof on chain through the gateway's fulfillDecryption callback
+//!
+//! Requests reach the oracle once their block is `confirmation_depth` deep, see
+//! `PendingDecryptions`. They are handled concurrently, only the gateway transaction is
+//! sent one at a time (the relayer wallet has a single nonce stream).
+//! A request only counts as processed once it was fulfilled, failures are retried a few
+//! times and then given up on.
+
+use crate::config::OracleConfig;
+use crate::state::SharedState;
+use crate::types::{DecryptionRequest, Handle};
+use alloy::network::EthereumWallet;
+use alloy::primitives::{Bytes, B256, U256};
+use alloy::providers::{DynProvider, Provider, ProviderBuilder, WsConnect};
+use alloy::signers::local::PrivateKeySigner;
+use alloy::sol;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::{Mutex, Semaphore};
+
+/// Attempts at fulfilling a request before giving up on it
+const FULFILL_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Requests handled at the same time, further ones wait for a slot
+const MAX_CONCURRENT_REQUESTS: usize = 16;
+
+sol! {
+    #[sol(rpc)]
+    contract DecryptionGateway {
+        function fulfillDecryption(bytes32[] calldata handles, uint256[] calldata plaintexts, bytes calldata decryptionProof) external;
+    }
+}
+
+#[derive(Serialize)]
+struct DecryptRequest<'a> {
+    handles: &'a [Handle],
+}
+
+#[derive(Deserialize)]
+struct DecryptResponse {
+    plaintexts: Vec<PlaintextPayload>,
+    decryption_proof: Bytes,
+}
+
+#[derive(Deserialize)]
+struct PlaintextPayload {
+    handle: Handle,
+    value: U256,
+}
+
+/// ACL requests held back until their block is `confirmation_depth` deep
+/// A request of a reorged block is dropped before it reaches the oracle.
+#[derive(Default)]
+pub struct PendingDecryptions {
+    requests: Vec<DecryptionRequest>,
+}
+
+impl PendingDecryptions {
+    pub fn hold(&mut self, request: DecryptionRequest) {
+        self.requests.push(request);
+    }
+
+    /// Drop the requests of a block that was reorged out
+    pub fn drop_block(&mut self, block_hash: Option<B256>) -> usize {
+        let before = self.requests.len();
+        self.requests.retain(|r| r.block_hash != block_hash);
+        before - self.requests.len()
+    }
+
+    /// Take the requests whose block is `depth` deep at `head`
+    pub fn take_confirmed(&mut self, head: u64, depth: u64) -> Vec<DecryptionRequest> {
+        let Some(cutoff) = head.checked_sub(depth) else {
+            return Vec::new();
+        };
+        let (confirmed, pending) = std::mem::take(&mut self.requests)
+            .into_iter()
+            .partition(|r| r.metadata.block_number <= cutoff);
+        self.requests = pending;
+        confirmed
+    }
+}
+
+pub struct DecryptionOracle {
+    state: SharedState,
+    config: OracleConfig,
+    http: reqwest::Client,
+    provider: DynProvider,
+    /// Requests being handled, by (tx_hash, log_index), so a redelivered log isn't handled twice
+    in_flight: Mutex<HashSet<(Option<B256>, u64)>>,
+    /// Held while sending a gateway transaction
+    relayer: Mutex<()>,
+}
+
+impl DecryptionOracle {
+    pub async fn new(state: SharedState, config: OracleConfig, websocket_url: &str) -> Result<Self> {
+        let signer: PrivateKeySigner = config
+            .private_key
+            .parse()
+            .context("ORACLE_PRIVATE_KEY is not a valid private key")?;
+        println!("[Oracle] relayer address: {}", signer.address());
+        let provider = ProviderBuilder::new()
+            .wallet(EthereumWallet::from(signer))
+            .connect_ws(WsConnect::new(websocket_url))
+            .await
+            .context("Failed to connect oracle provider")?
+            .erased();
+
+        Ok(Self {
+            state,
+            config,
+            http: reqwest::Client::new(),
+            provider,
+            in_flight: Mutex::new(HashSet::new()),
+            relayer: Mutex::new(()),
+        })
+    }
+
+    /// Handle decryption requests concurrently, up to `MAX_CONCURRENT_REQUESTS` at a time
+    pub async fn run(self, mut requests: UnboundedReceiver<DecryptionRequest>) {
+        let oracle = Arc::new(self);
+        let slots = Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS));
+        while let Some(request) = requests.recv().await {
+            println!(
+                "[Oracle] AllowedForDecryption tx={:?} handles={}",
+                request.metadata.tx_hash,
+                request.handles.len()
+            );
+            let key = (request.metadata.tx_hash, request.metadata.log_index);
+            // The same log may have been delivered again while the first one is in flight
+            if oracle.state.processed.read().await.is_processed(&request.metadata)
+                || !oracle.in_flight.lock().await.insert(key)
+            {
+                continue;
+            }
+            let Ok(slot) = slots.clone().acquire_owned().await else {
+                break;
+            };
+            let oracle = oracle.clone();
+            tokio::spawn(async move {
+                oracle.handle(&request).await;
+                oracle.in_flight.lock().await.remove(&key);
+                drop(slot);
+            });
+        }
+    }
+
+    async fn handle(&self, request: &DecryptionRequest) {
+        for attempt in 1..=FULFILL_ATTEMPTS {
+            match self.fulfill(request).await {
+                Ok(()) => {
+                    let marked = self
+                        .state
+                        .processed
+                        .write()
+                        .await
+                        .mark_processed(&request.metadata, request.block_hash);
+                    if let Err(e) = marked {
+                        println!("[Oracle] failed to record request tx={:?}: {:#}", request.metadata.tx_hash, e);
+                    }
+                    return;
+                }
+                Err(e) => {
+                    println!(
+                        "[Oracle] request tx={:?} failed (attempt {}/{}): {:#}",
+                        request.metadata.tx_hash, attempt, FULFILL_ATTEMPTS, e
+                    );
+                    if attempt < FULFILL_ATTEMPTS {
+                        tokio::time::sleep(RETRY_DELAY).await;
+                    }
+                }
+            }
+        }
+        println!("[Oracle] giving up on request tx={:?}", request.metadata.tx_hash);
+    }
+
+    async fn fulfill(&self, request: &DecryptionRequest) -> Result<()> {
+        self.wait_final(&request.handles).await?;
+        let (plaintexts, proof) = self.decrypt(&request.handles).await?;
+
+        let gateway = DecryptionGateway::new(self.config.gateway_address, &self.provider);
+        let pending = {
+            let _relayer = self.relayer.lock().await;
+            gateway
+                .fulfillDecryption(request.handles.clone(), plaintexts, proof)
+                .send()
+                .await?
+        };
+        let receipt = pending.get_receipt().await?;
+        println!(
+            "[Oracle] fulfilled {} handle(s) in tx {:?}",
+            request.handles.len(),
+            receipt.transaction_hash
+        );
+        Ok(())
+    }
+
+    /// Wait until every handle has a final ciphertext, or give up after the configured timeout
+    /// A handle whose op failed never gets one, the request fails right away.
+    async fn wait_final(&self, handles: &[Handle]) -> Result<()> {
+        let deadline = Instant::now() + self.config.timeout;
+        loop {
+            let missing: Vec<Handle> = {
+                let store = self.state.store.read().await;
+                for handle in handles {
+                    if let Some(Err(reason)) = store.get_final(handle).map(|r| &r.outcome) {
+                        bail!("handle {} has no ciphertext: {}", handle, reason);
+                    }
+                }
+                handles
+                    .iter()
+                    .filter(|handle| store.get_final(handle).is_none())
+                    .copied()
+                    .collect()
+            };
+
+            if missing.is_empty() {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                bail!("no final ciphertext for handle(s) {:?}", missing);
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Ask the KMS to decrypt, returning plaintexts in the same order as the request
+    /// together with the KMS signed decryption proof over them
+    async fn decrypt(&self, handles: &[Handle]) -> Result<(Vec<U256>, Bytes)> {
+        let mut request = self
+            .http
+            .post(format!("{}/decrypt", self.config.kms_url))
+            .json(&DecryptRequest { handles });
+        if let Some(token) = &self.config.kms_token {
+            request = request.bearer_auth(token);
+        }
+        let response: DecryptResponse = request
+            .send()
+            .await?
+            .error_for_status()
+            .context("KMS rejected decryption request")?
+            .json()
+            .await?;
+
+        let plaintexts = handles
+            .iter()
+            .map(|handle| {
+                response
+                    .plaintexts
+                    .iter()
+                    .find(|p| p.handle == *handle)
+                    .map(|p| p.value)
+                    .ok_or_else(|| anyhow!("KMS returned no plaintext for {}", handle))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok((plaintexts, response.decryption_proof))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EventMetadata;
+    use alloy::primitives::Address;
+
+    fn request(block_number: u64, block: u8) -> DecryptionRequest {
+        DecryptionRequest {
+            metadata: EventMetadata {
+                block_number,
+                tx_hash: Some(B256::repeat_byte(block)),
+                log_index: 0,
+                caller: Address::ZERO,
+            },
+            handles: vec![B256::repeat_byte(1)],
+            block_hash: Some(B256::repeat_byte(block)),
+        }
+    }
+
+    #[test]
+    fn test_requests_wait_for_confirmations_and_reorgs() {
+        let mut pending = PendingDecryptions::default();
+        pending.hold(request(10, 0xaa));
+        pending.hold(request(11, 0xbb));
+        pending.hold(request(12, 0xcc));
+
+        assert!(pending.take_confirmed(11, 2).is_empty());
+        assert_eq!(pending.drop_block(Some(B256::repeat_byte(0xbb))), 1);
+        let confirmed = pending.take_confirmed(13, 2);
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].metadata.block_number, 10);
+        assert_eq!(pending.take_confirmed(14, 2).len(), 1);
+    }
+}
@@ -0,0 +1,256 @@
+//! Caller Policy
+//! Decides which caller contracts (`metadata.caller`) the coprocessor serves, and routes
+//! accepted callers to a priority class with a per-block op budget.
+//! Ops over budget are deferred to the following blocks rather than dropped: later ops
+//! may read their results.
+//!
+//! Loaded from the JSON file at `CALLER_POLICY_PATH`:
+//! ```json
+//! {
+//!   "mode": "allowlist",
+//!   "callers": {
+//!     "0x5FbDB2315678afecb367f032d93F642f64180aa3": { "priority": "high", "max_ops_per_block": 500 }
+//!   },
+//!   "denied": [],
+//!   "default": { "priority": "low", "max_ops_per_block": 50 }
+//! }
+//! ```
+
+use crate::events::types::{EventMetadata, FheOperation};
+use alloy::primitives::{Address, B256};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+/// Blocks of budget usage kept around for late (backfilled) logs
+const BUDGET_WINDOW: u64 = 64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyMode {
+    /// Only callers listed in `callers` are served
+    Allowlist,
+    /// Everyone except callers listed in `denied` is served
+    #[default]
+    Denylist,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct CallerRoute {
+    #[serde(default)]
+    pub priority: Priority,
+    /// Ops accepted from this caller per block, unlimited if unset
+    pub max_ops_per_block: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CallerPolicy {
+    #[serde(default)]
+    pub mode: PolicyMode,
+    #[serde(default)]
+    pub callers: HashMap<Address, CallerRoute>,
+    #[serde(default)]
+    pub denied: Vec<Address>,
+    /// Route for callers not listed in `callers` (denylist mode only)
+    #[serde(default)]
+    pub default: CallerRoute,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Accept(Priority),
+    /// The caller's budget for the block is spent, hand the op to `Admission::defer`
+    Defer,
+    Reject(&'static str),
+}
+
+impl CallerPolicy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read caller policy {}", path.display()))?;
+        serde_json::from_str(&raw)
+            .with_context(|| format!("Invalid caller policy {}", path.display()))
+    }
+
+    /// Route for `caller`, or None if the policy doesn't serve it
+    pub fn route(&self, caller: &Address) -> Option<CallerRoute> {
+        if self.denied.contains(caller) {
+            return None;
+        }
+        match (self.mode, self.callers.get(caller)) {
+            (_, Some(route)) => Some(*route),
+            (PolicyMode::Allowlist, None) => None,
+            (PolicyMode::Denylist, None) => Some(self.default),
+        }
+    }
+}
+
+/// Applies a `CallerPolicy` and tracks per-block budget usage per caller
+pub struct Admission {
+    policy: CallerPolicy,
+    used: HashMap<(Address, u64), u64>,
+    latest_block: u64,
+    /// Over budget ops in arrival order, with the hash of the block they were seen in
+    deferred: VecDeque<(FheOperation, Option<B256>)>,
+}
+
+impl Admission {
+    pub fn new(policy: CallerPolicy) -> Self {
+        Self {
+            policy,
+            used: HashMap::new(),
+            latest_block: 0,
+            deferred: VecDeque::new(),
+        }
+    }
+
+    /// Decide whether an op from `metadata.caller` enters the queue, and at which priority
+    pub fn admit(&mut self, metadata: &EventMetadata) -> Decision {
+        let Some(route) = self.policy.route(&metadata.caller) else {
+            return Decision::Reject("caller not allowed");
+        };
+        if self.charge(metadata.caller, metadata.block_number, route) {
+            Decision::Accept(route.priority)
+        } else {
+            Decision::Defer
+        }
+    }
+
+    /// Keep an op `admit` deferred until a block with budget left for its caller
+    pub fn defer(&mut self, op: FheOperation, block_hash: Option<B256>) {
+        self.deferred.push_back((op, block_hash));
+    }
+
+    /// Deferred ops that fit their caller's budget of block `block_number`, oldest first
+    /// They are charged before the block's own ops, so a backlog drains in order.
+    pub fn release(&mut self, block_number: u64) -> Vec<(Priority, FheOperation, Option<B256>)> {
+        let mut released = Vec::new();
+        let mut kept = VecDeque::new();
+        for (op, block_hash) in std::mem::take(&mut self.deferred) {
+            let Some(caller) = op.metadata().map(|m| m.caller) else {
+                continue;
+            };
+            let Some(route) = self.policy.route(&caller) else {
+                continue;
+            };
+            if self.charge(caller, block_number, route) {
+                released.push((route.priority, op, block_hash));
+            } else {
+                kept.push_back((op, block_hash));
+            }
+        }
+        self.deferred = kept;
+        released
+    }
+
+    /// Drop deferred ops of a block that was reorged out
+    pub fn forget_block(&mut self, block_hash: Option<B256>) -> usize {
+        let before = self.deferred.len();
+        self.deferred.retain(|(_, hash)| *hash != block_hash);
+        before - self.deferred.len()
+    }
+
+    /// Count one op against `caller`'s budget of `block_number`, false if it is spent
+    fn charge(&mut self, caller: Address, block_number: u64, route: CallerRoute) -> bool {
+        if block_number > self.latest_block {
+            self.latest_block = block_number;
+            let oldest = self.latest_block.saturating_sub(BUDGET_WINDOW);
+            self.used.retain(|(_, block), _| *block >= oldest);
+        }
+
+        let used = self.used.entry((caller, block_number)).or_insert(0);
+        if let Some(max) = route.max_ops_per_block {
+            if *used >= max {
+                return false;
+            }
+        }
+        *used += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::types::{FheType, TrivialEncrypt};
+    use alloy::primitives::U256;
+
+    fn trivial(metadata: EventMetadata) -> FheOperation {
+        FheOperation::TrivialEncrypt(TrivialEncrypt {
+            metadata,
+            plaintext: U256::from(1),
+            to_type: FheType::Uint8,
+            result: B256::repeat_byte(1),
+        })
+    }
+
+    fn metadata(caller: Address, block_number: u64) -> EventMetadata {
+        EventMetadata {
+            block_number,
+            tx_hash: None,
+            log_index: 0,
+            caller,
+        }
+    }
+
+    #[test]
+    fn test_allowlist_routing_and_budget() {
+        let token = Address::repeat_byte(1);
+        let spam = Address::repeat_byte(2);
+        let policy: CallerPolicy = serde_json::from_str(&format!(
+            r#"{{"mode": "allowlist", "callers": {{"{}": {{"priority": "high", "max_ops_per_block": 2}}}}}}"#,
+            token
+        ))
+        .unwrap();
+        let mut admission = Admission::new(policy);
+
+        assert_eq!(admission.admit(&metadata(token, 1)), Decision::Accept(Priority::High));
+        assert_eq!(admission.admit(&metadata(token, 1)), Decision::Accept(Priority::High));
+        assert_eq!(admission.admit(&metadata(token, 1)), Decision::Defer);
+        admission.defer(trivial(metadata(token, 1)), None);
+        // Budget resets on the next block, where the deferred op goes first
+        let released = admission.release(2);
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].0, Priority::High);
+        assert_eq!(admission.admit(&metadata(token, 2)), Decision::Accept(Priority::High));
+        assert_eq!(admission.admit(&metadata(token, 2)), Decision::Defer);
+        assert_eq!(admission.admit(&metadata(spam, 2)), Decision::Reject("caller not allowed"));
+
+        // Deferred ops of a reorged block are dropped
+        let orphaned = Some(B256::repeat_byte(0xbb));
+        admission.defer(trivial(metadata(token, 2)), orphaned);
+        assert_eq!(admission.forget_block(orphaned), 1);
+        assert!(admission.deferred.is_empty());
+    }
+
+    #[test]
+    fn test_denylist_uses_default_route() {
+        let spam = Address::repeat_byte(2);
+        let policy = CallerPolicy {
+            denied: vec![spam],
+            default: CallerRoute {
+                priority: Priority::Low,
+                max_ops_per_block: None,
+            },
+            ..Default::default()
+        };
+        let mut admission = Admission::new(policy);
+
+        assert_eq!(
+            admission.admit(&metadata(Address::repeat_byte(3), 1)),
+            Decision::Accept(Priority::Low)
+        );
+        assert_eq!(admission.admit(&metadata(spam, 1)), Decision::Reject("caller not allowed"));
+    }
+}
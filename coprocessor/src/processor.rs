@@ -0,0 +1,237 @@
+//! FHE Operation Processor
+//! Runs each parsed operation as soon as its log arrives and records the result.
+use crate::executor::{self, Value};
+use crate::state::SharedState;
+use crate::store::{Ciphertext, ResultStatus};
+use crate::types::{FheOperation, VerifyInput};
+use alloy::primitives::B256;
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+
+#[derive(Clone)]
+pub struct Processor {
+    state: SharedState,
+    confirmation_depth: u64,
+}
+
+impl Processor {
+    pub fn new(state: SharedState, confirmation_depth: u64) -> Self {
+        Self {
+            state,
+            confirmation_depth,
+        }
+    }
+
+    /// Process an operation from a newly seen log
+    ///
+    /// Logs already in the processed index, or whose result is already final, are
+    /// skipped. With a confirmation depth of 0
+    /// results are final immediately, otherwise they stay speculative until
+    /// `finality::on_new_head` confirms the block.
+    /// Ops are processed one at a time by `run`, so the index is only locked to check and
+    /// to mark a log, never while the op computes.
+    pub async fn process(&self, op: &FheOperation, block_hash: Option<B256>) -> Result<()> {
+        let Some(metadata) = op.metadata() else {
+            return Ok(());
+        };
+        let finalized = match op.result_handle() {
+            Some(handle) => self.state.store.read().await.get_final(&handle).is_some(),
+            None => false,
+        };
+        if self.state.processed.read().await.is_processed(metadata) || finalized {
+            println!(
+                "[Processor] skipping duplicate {} tx={:?} log_index={}",
+                op.name(),
+                metadata.tx_hash,
+                metadata.log_index
+            );
+            return Ok(());
+        }
+
+        // A failed op is recorded as such, the ops reading its result then fail on it too
+        let outcome = match op {
+            FheOperation::VerifyInput(input) => match self.verify_input(input).await {
+                Ok(()) => self.compute(op).await,
+                Err(e) => {
+                    println!(
+                        "[Processor] rejected input {} from user {} tx={:?}: {:#}",
+                        input.result, input.user_address, metadata.tx_hash, e
+                    );
+                    Err(e.context("input proof rejected"))
+                }
+            },
+            _ => self.compute(op).await,
+        };
+        if let Err(e) = &outcome {
+            println!(
+                "[Processor] failed to compute {} tx={:?}: {:#}",
+                op.name(),
+                metadata.tx_hash,
+                e
+            );
+        }
+
+        let status = if self.confirmation_depth == 0 {
+            ResultStatus::Final
+        } else {
+            ResultStatus::Speculative
+        };
+        let computed = outcome.is_ok();
+        self.state
+            .store
+            .write()
+            .await
+            .insert(op, outcome.map_err(|e| format!("{:#}", e)), block_hash, status)?;
+        if computed && self.state.hcu.write().await.record(op, block_hash) {
+            println!(
+                "[Processor] tx {:?} from caller {} exceeded the per-tx HCU limit",
+                metadata.tx_hash, metadata.caller
+            );
+        }
+        self.state
+            .processed
+            .write()
+            .await
+            .mark_processed(metadata, block_hash)
+    }
+
+    /// Check the input's proof against the contract that submitted it and the user
+    /// it was encrypted for, on the blocking pool as verification is expensive
+    async fn verify_input(&self, input: &VerifyInput) -> Result<()> {
+        let verifier = self
+            .state
+            .input_verifier
+            .read()
+            .await
+            .clone()
+            .ok_or_else(|| anyhow!("input verifier not loaded"))?;
+        let proof = input.input_proof.clone();
+        let (contract, user) = (input.metadata.caller, input.user_address);
+        tokio::task::spawn_blocking(move || verifier.verify(&proof, contract, user)).await?
+    }
+
+    /// Compute the op's ciphertext under the KMS server key, from the stored results of
+    /// its inputs, on the blocking pool
+    async fn compute(&self, op: &FheOperation) -> Result<Ciphertext> {
+        let inputs = {
+            let store = self.state.store.read().await;
+            op.input_handles()
+                .iter()
+                .map(|handle| {
+                    let input = store
+                        .get(handle)
+                        .ok_or_else(|| anyhow!("input {} has no result", handle))?;
+                    match &input.outcome {
+                        Ok(ciphertext) => Ok((ciphertext.fhe_type, ciphertext.bytes.clone())),
+                        Err(reason) => Err(anyhow!("input {} failed: {}", handle, reason)),
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+        let (key, key_id) = self
+            .state
+            .server_key
+            .read()
+            .await
+            .as_ref()
+            .map(|loaded| (loaded.key.clone(), loaded.key_id.clone()))
+            .ok_or_else(|| anyhow!("server key not loaded"))?;
+        let op = op.clone();
+        tokio::task::spawn_blocking(move || {
+            // The server key is per thread in tfhe, and blocking pool threads are reused
+            tfhe::set_server_key((*key).clone());
+            let inputs = inputs
+                .iter()
+                .map(|(fhe_type, bytes)| Value::deserialize(*fhe_type, bytes))
+                .collect::<Result<Vec<_>>>()?;
+            let result = executor::execute(&op, inputs)?;
+            Ok(Ciphertext {
+                fhe_type: result.fhe_type(),
+                bytes: result.serialize()?,
+                key_id,
+            })
+        })
+        .await?
+    }
+
+    /// Drain the op queue in priority order
+    /// Inputs can't be verified nor ops computed before the KMS keys are in, so nothing is
+    /// processed until then
+    pub async fn run(self) {
+        while self.state.input_verifier.read().await.is_none() {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        loop {
+            let item = self.state.queue.pop().await;
+            if let Err(e) = self.process(&item.op, item.block_hash).await {
+                println!("[Processor] failed to process {}: {}", item.op.name(), e);
+            }
+        }
+    }
+
+    /// A log was removed by a reorg, drop everything computed from its block
+    /// (including ops still waiting in the queue)
+    pub async fn revert_block(&self, block_hash: Option<B256>) -> Result<()> {
+        self.state.queue.remove_block(block_hash).await;
+        self.state.processed.write().await.forget_block(block_hash)?;
+        self.state.hcu.write().await.forget_block(block_hash);
+        let dropped = self.state.store.write().await.discard_block(block_hash)?;
+        if dropped > 0 {
+            println!(
+                "[Processor] reorg: discarded {} speculative result(s) from block {:?}",
+                dropped, block_hash
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::CoprocessorState;
+    use crate::types::{BinaryOp, BinaryOpType, EventMetadata, FheType};
+    use alloy::primitives::Address;
+
+    fn metadata(log_index: u64) -> EventMetadata {
+        EventMetadata {
+            block_number: 1,
+            tx_hash: Some(B256::repeat_byte(0x11)),
+            log_index,
+            caller: Address::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failures_reach_dependent_ops() {
+        let state = CoprocessorState::new(0);
+        let processor = Processor::new(state.clone(), 0);
+        let input = B256::repeat_byte(1);
+        let sum = B256::repeat_byte(2);
+
+        // No verifier is loaded, so the input is rejected
+        let verify = FheOperation::VerifyInput(VerifyInput {
+            metadata: metadata(0),
+            input_handle: input,
+            user_address: Address::ZERO,
+            input_proof: Vec::new(),
+            input_type: FheType::Uint8,
+            result: input,
+        });
+        processor.process(&verify, None).await.unwrap();
+        let add = FheOperation::Binary(BinaryOp {
+            metadata: metadata(1),
+            op_type: BinaryOpType::Add,
+            lhs: input,
+            rhs: input,
+            scalar_byte: 0,
+            result: sum,
+        });
+        processor.process(&add, None).await.unwrap();
+
+        let store = state.store.read().await;
+        let reason = store.get_final(&sum).unwrap().outcome.as_ref().unwrap_err();
+        assert!(reason.contains("failed: input proof rejected"), "{}", reason);
+        assert!(state.processed.read().await.is_processed(&metadata(1)));
+    }
+}
@@ -0,0 +1,141 @@
+//! Operation Queue
+//! Priority queue between the listener and the processor. Higher priority callers are
+//! served first; within a priority class ops keep their arrival order. An op whose input
+//! is the result of another queued op waits for that op, whatever its priority.
+
+use crate::policy::Priority;
+use crate::types::{FheOperation, Handle};
+use alloy::primitives::B256;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use tokio::sync::{Mutex, Notify};
+
+pub struct QueuedOp {
+    pub priority: Priority,
+    pub seq: u64,
+    pub op: FheOperation,
+    pub block_hash: Option<B256>,
+}
+
+impl PartialEq for QueuedOp {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+
+impl Eq for QueuedOp {}
+
+impl Ord for QueuedOp {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap: highest priority first, then lowest sequence number
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for QueuedOp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Default)]
+pub struct OpQueue {
+    heap: Mutex<BinaryHeap<QueuedOp>>,
+    notify: Notify,
+    next_seq: AtomicU64,
+}
+
+impl OpQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn push(&self, priority: Priority, op: FheOperation, block_hash: Option<B256>) {
+        let seq = self.next_seq.fetch_add(1, AtomicOrdering::Relaxed);
+        self.heap.lock().await.push(QueuedOp {
+            priority,
+            seq,
+            op,
+            block_hash,
+        });
+        self.notify.notify_one();
+    }
+
+    /// Wait for the next op in priority order among those whose inputs are ready
+    pub async fn pop(&self) -> QueuedOp {
+        loop {
+            if let Some(item) = Self::pop_ready(&mut *self.heap.lock().await) {
+                return item;
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// Highest priority op none of whose inputs is still to be computed by a queued op
+    fn pop_ready(heap: &mut BinaryHeap<QueuedOp>) -> Option<QueuedOp> {
+        let pending: HashSet<Handle> = heap.iter().filter_map(|item| item.op.result_handle()).collect();
+        let mut items = std::mem::take(heap).into_vec();
+        // Result handles are unique so some op is always ready, the fallback is only defensive
+        let next = items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| !item.op.input_handles().iter().any(|h| pending.contains(h)))
+            .max_by(|(_, a), (_, b)| a.cmp(b))
+            .or_else(|| items.iter().enumerate().max_by(|(_, a), (_, b)| a.cmp(b)))
+            .map(|(index, _)| index);
+        let item = next.map(|index| items.swap_remove(index));
+        *heap = items.into();
+        item
+    }
+
+    /// Drop queued ops from a block that was reorged out before they ran
+    pub async fn remove_block(&self, block_hash: Option<B256>) -> usize {
+        let mut heap = self.heap.lock().await;
+        let before = heap.len();
+        heap.retain(|item| item.block_hash != block_hash);
+        before - heap.len()
+    }
+
+    pub async fn len(&self) -> usize {
+        self.heap.lock().await.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{EventMetadata, UnaryOp, UnaryOpType};
+    use alloy::primitives::Address;
+
+    fn neg(input: u8, result: u8) -> FheOperation {
+        FheOperation::Unary(UnaryOp {
+            metadata: EventMetadata {
+                block_number: 1,
+                tx_hash: None,
+                log_index: 0,
+                caller: Address::ZERO,
+            },
+            op_type: UnaryOpType::Neg,
+            ct: B256::repeat_byte(input),
+            result: B256::repeat_byte(result),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_inputs_before_priority() {
+        let queue = OpQueue::new();
+        queue.push(Priority::Low, neg(1, 2), None).await;
+        // Reads the low priority op's result
+        queue.push(Priority::High, neg(2, 3), None).await;
+        queue.push(Priority::Normal, neg(9, 10), None).await;
+
+        let order: Vec<Priority> = [queue.pop().await, queue.pop().await, queue.pop().await]
+            .iter()
+            .map(|item| item.priority)
+            .collect();
+        assert_eq!(order, vec![Priority::Normal, Priority::Low, Priority::High]);
+    }
+}
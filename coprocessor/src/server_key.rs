@@ -0,0 +1,222 @@
+//! Server key bootstrap
+//! Fetches the compressed server key of the KMS's active keyset and decompresses it
+//! locally, a fraction of the download the full key from /keys/server would be.
+//! The processor computes every operation under it.
+//! The public key and CRS input proofs are verified against are loaded along with it.
+use crate::input_verifier::InputVerifier;
+use crate::state::SharedState;
+use anyhow::{anyhow, Context, Result};
+use common::fingerprint::{check_fingerprint, FingerprintMismatch};
+use reqwest::header::HeaderMap;
+use reqwest::Response;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tfhe::{CompressedServerKey, ServerKey};
+
+/// Preset the KMS generates keysets with unless told otherwise
+pub const DEFAULT_TFHE_PARAMS: &str = "tuniform-2m64";
+
+/// Preset of keysets the KMS imported from its old flat layout (tfhe's default config)
+/// Operations still compute under them, but they have no CRS so inputs are refused.
+pub const LEGACY_TFHE_PARAMS: &str = "default";
+
+/// KMS the keys are downloaded from
+#[derive(Clone)]
+pub struct KmsSource {
+    pub url: String,
+    /// Bearer token of the coprocessor role, server keys are not public
+    pub token: Option<String>,
+}
+
+impl KmsSource {
+    /// GET one of the KMS's binary key endpoints (`path` without leading slash)
+    pub async fn download(&self, path: &str) -> Result<Response> {
+        let mut request = reqwest::Client::new().get(format!("{}/{}", self.url, path));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        Ok(request.send().await?.error_for_status()?)
+    }
+}
+
+/// Expected sha256 or keccak256 hex of each downloaded key, as listed by GET /keys/{id}/info
+/// on the KMS. Keys without a pin are taken as served.
+#[derive(Debug, Clone, Default)]
+pub struct KeyPins {
+    pub server_key: Option<String>,
+    pub public_key: Option<String>,
+    pub crs: Option<String>,
+}
+
+impl KeyPins {
+    /// Which keys are pinned, for the startup banner
+    pub fn describe(&self) -> String {
+        let pinned: Vec<&str> = [
+            ("server key", &self.server_key),
+            ("public key", &self.public_key),
+            ("CRS", &self.crs),
+        ]
+        .iter()
+        .filter(|(_, pin)| pin.is_some())
+        .map(|(name, _)| *name)
+        .collect();
+        if pinned.is_empty() {
+            "nothing pinned".to_string()
+        } else {
+            format!("pinned: {}", pinned.join(", "))
+        }
+    }
+}
+
+/// The KMS's active keyset uses another parameter preset than the configured one
+#[derive(Debug)]
+pub struct ParamsMismatch {
+    pub expected: String,
+    pub served: String,
+}
+
+impl fmt::Display for ParamsMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "KMS keyset uses the {} parameter preset, expected {} (TFHE_PARAMS)",
+            self.served, self.expected
+        )
+    }
+}
+
+impl std::error::Error for ParamsMismatch {}
+
+/// Decompressed server key and the keyset it belongs to
+pub struct LoadedServerKey {
+    pub key_id: String,
+    /// Parameter preset of the keyset, as reported by the KMS
+    pub params: String,
+    pub compressed_bytes: usize,
+    pub decompress_ms: u128,
+    /// Set on the blocking threads that compute operations
+    pub key: Arc<ServerKey>,
+}
+
+/// Download and decompress the active server key, decompression runs on the blocking pool
+/// The key comes from the binary endpoint, keyset id and preset from its headers.
+/// A keyset of another preset than `expected_params`, or a key whose fingerprint differs
+/// from `pinned`, is refused before decompression.
+pub async fn fetch(kms: &KmsSource, pinned: Option<&str>, expected_params: &str) -> Result<LoadedServerKey> {
+    let response = kms
+        .download("keys/server/compressed/raw")
+        .await
+        .context("KMS has no compressed server key for its active keyset")?;
+    let key_id = header(response.headers(), "x-key-id")?;
+    let params = header(response.headers(), "x-key-params")?;
+    if params != expected_params {
+        return Err(ParamsMismatch {
+            expected: expected_params.to_string(),
+            served: params,
+        }
+        .into());
+    }
+    let bytes = response.bytes().await?;
+    if let Some(pinned) = pinned {
+        check_fingerprint("server key", &bytes, pinned)?;
+    }
+    let compressed_bytes = bytes.len();
+
+    let started = Instant::now();
+    let key = tokio::task::spawn_blocking(move || -> Result<ServerKey> {
+        let compressed: CompressedServerKey = bincode::deserialize(&bytes)?;
+        Ok(compressed.decompress())
+    })
+    .await??;
+
+    Ok(LoadedServerKey {
+        key_id,
+        params,
+        compressed_bytes,
+        decompress_ms: started.elapsed().as_millis(),
+        key: Arc::new(key),
+    })
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Result<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("KMS response has no {} header", name))
+}
+
+/// Fetch the server key into the shared state, retrying while the KMS has no keyset yet
+/// Keys of another preset or that don't match their pinned fingerprint are an error,
+/// retrying won't fix those.
+pub async fn bootstrap(state: SharedState, kms: KmsSource, pins: KeyPins, params: String) -> Result<()> {
+    let permanent = |e: &anyhow::Error| e.is::<FingerprintMismatch>() || e.is::<ParamsMismatch>();
+    loop {
+        let loaded = match fetch(&kms, pins.server_key.as_deref(), &params).await {
+            Ok(loaded) => loaded,
+            Err(e) if permanent(&e) => return Err(e),
+            Err(e) => {
+                println!("[ServerKey] fetch from {} failed, retrying in 10s: {:#}", kms.url, e);
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                continue;
+            }
+        };
+        match InputVerifier::fetch(&kms, &loaded.key_id, &loaded.params, &pins).await {
+            Ok(verifier) => {
+                println!(
+                    "[ServerKey] keyset {} ({}) loaded: {} compressed bytes, decompressed in {} ms",
+                    loaded.key_id, loaded.params, loaded.compressed_bytes, loaded.decompress_ms
+                );
+                *state.input_verifier.write().await = Some(Arc::new(verifier));
+                *state.server_key.write().await = Some(loaded);
+                return Ok(());
+            }
+            Err(e) if permanent(&e) => return Err(e),
+            Err(e) => {
+                println!("[ServerKey] input verifier fetch from {} failed, retrying in 10s: {:#}", kms.url, e);
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::CoprocessorState;
+    use axum::{routing::get, Router};
+
+    async fn serve_kms(params: &'static str) -> String {
+        let kms = Router::new().route(
+            "/keys/server/compressed/raw",
+            get(move || async move { ([("x-key-id", "1-aa"), ("x-key-params", params)], "not the pinned key") }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, kms).await });
+        url
+    }
+
+    async fn bootstrap_error(url: String, pins: KeyPins) -> anyhow::Error {
+        let state = CoprocessorState::new(0);
+        let kms = KmsSource { url, token: None };
+        let bootstrap = bootstrap(state.clone(), kms, pins, DEFAULT_TFHE_PARAMS.to_string());
+        let result = tokio::time::timeout(Duration::from_secs(5), bootstrap).await;
+        assert!(state.server_key.read().await.is_none());
+        result.expect("bootstrap kept retrying").unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_stops_on_mismatch() {
+        let pins = KeyPins {
+            server_key: Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string()),
+            ..KeyPins::default()
+        };
+        let error = bootstrap_error(serve_kms(DEFAULT_TFHE_PARAMS).await, pins).await;
+        assert!(error.is::<FingerprintMismatch>());
+
+        let error = bootstrap_error(serve_kms("compact-pke-2m64").await, KeyPins::default()).await;
+        assert!(error.is::<ParamsMismatch>());
+    }
+}
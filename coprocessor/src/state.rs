@@ -0,0 +1,50 @@
+//! Shared coprocessor state
+use crate::dedup::ProcessedEvents;
+use crate::hcu::HcuTracker;
+use crate::input_verifier::InputVerifier;
+use crate::queue::OpQueue;
+use crate::server_key::LoadedServerKey;
+use crate::store::ResultStore;
+use anyhow::Result;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub type SharedState = Arc<CoprocessorState>;
+
+pub struct CoprocessorState {
+    pub store: RwLock<ResultStore>,
+    pub processed: RwLock<ProcessedEvents>,
+    pub queue: OpQueue,
+    pub hcu: RwLock<HcuTracker>,
+    /// Server key of the KMS's active keyset, None until the bootstrap finished
+    pub server_key: RwLock<Option<LoadedServerKey>>,
+    /// Checks input proofs against the same keyset, loaded with the server key
+    pub input_verifier: RwLock<Option<Arc<InputVerifier>>>,
+}
+
+impl CoprocessorState {
+    /// State kept in memory only
+    #[cfg(test)]
+    pub fn new(hcu_tx_limit: u64) -> SharedState {
+        Self::with(ResultStore::new(), ProcessedEvents::new(), hcu_tx_limit)
+    }
+
+    /// State whose results and processed event index are persisted under `data_dir`
+    pub fn open(data_dir: &Path, hcu_tx_limit: u64) -> Result<SharedState> {
+        let store = ResultStore::load(&data_dir.join("results"))?;
+        let processed = ProcessedEvents::load(&data_dir.join("processed_events.json"))?;
+        Ok(Self::with(store, processed, hcu_tx_limit))
+    }
+
+    fn with(store: ResultStore, processed: ProcessedEvents, hcu_tx_limit: u64) -> SharedState {
+        Arc::new(Self {
+            store: RwLock::new(store),
+            processed: RwLock::new(processed),
+            queue: OpQueue::new(),
+            hcu: RwLock::new(HcuTracker::new(hcu_tx_limit)),
+            server_key: RwLock::new(None),
+            input_verifier: RwLock::new(None),
+        })
+    }
+}
@@ -0,0 +1,172 @@
+//! Status API
+//! Small HTTP server exposing coprocessor progress, HCU totals and final ciphertexts.
+//! Ciphertexts are only served to the KMS, which presents COPROCESSOR_API_TOKEN.
+use crate::state::SharedState;
+use alloy::primitives::B256;
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use axum::{
+    extract::{Path, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+pub fn create_router(state: SharedState, ciphertext_token: Option<String>) -> Router {
+    let ciphertexts = Router::new()
+        .route("/ciphertexts/{handle}", get(ciphertext))
+        .route_layer(middleware::from_fn_with_state(Arc::new(ciphertext_token), require_token));
+    Router::new()
+        .route("/status", get(status))
+        .route("/hcu/callers", get(hcu_callers))
+        .route("/hcu/tx/{tx_hash}", get(hcu_tx))
+        .merge(ciphertexts)
+        .with_state(state)
+}
+
+pub async fn serve(state: SharedState, port: u16, ciphertext_token: Option<String>) -> Result<()> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    println!("[Status] serving on {}", addr);
+    let router = create_router(state, ciphertext_token);
+    axum::serve(tokio::net::TcpListener::bind(addr).await?, router).await?;
+    Ok(())
+}
+
+/// Let a request through only with the configured bearer token
+/// Without a token configured nothing gets through. Tokens are compared by digest so the
+/// comparison doesn't depend on where they first differ.
+async fn require_token(State(token): State<Arc<Option<String>>>, request: Request, next: Next) -> Response {
+    let presented = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match (token.as_deref(), presented) {
+        (Some(expected), Some(presented)) if Sha256::digest(expected) == Sha256::digest(presented) => {
+            next.run(request).await
+        }
+        _ => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+async fn status(State(state): State<SharedState>) -> Json<Value> {
+    let (speculative, finalized) = state.store.read().await.counts();
+    let queued = state.queue.len().await;
+    let hcu = state.hcu.read().await;
+    let server_key = state.server_key.read().await.as_ref().map(|key| {
+        json!({
+            "key_id": key.key_id,
+            "params": key.params,
+            "compressed_bytes": key.compressed_bytes,
+            "decompress_ms": key.decompress_ms,
+        })
+    });
+    Json(json!({
+        "results": { "speculative": speculative, "final": finalized },
+        "queued_ops": queued,
+        "hcu": {
+            "tx_limit": hcu.tx_limit(),
+            "callers": hcu.callers(),
+            "over_budget_txs": hcu.over_budget_txs(),
+        },
+        "server_key": server_key,
+    }))
+}
+
+async fn hcu_callers(State(state): State<SharedState>) -> Json<Value> {
+    Json(json!(state.hcu.read().await.callers()))
+}
+
+async fn hcu_tx(
+    State(state): State<SharedState>,
+    Path(tx_hash): Path<B256>,
+) -> Result<Json<Value>, StatusCode> {
+    let hcu = state.hcu.read().await;
+    let usage = hcu.tx(&tx_hash).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(json!(usage)))
+}
+
+/// Final ciphertext for a handle, used by the KMS for decryption
+/// Speculative and failed results are reported as 404. `key_id` names the keyset the
+/// KMS must decrypt it under.
+async fn ciphertext(
+    State(state): State<SharedState>,
+    Path(handle): Path<B256>,
+) -> Result<Json<Value>, StatusCode> {
+    let store = state.store.read().await;
+    let result = store.get_final(&handle).ok_or(StatusCode::NOT_FOUND)?;
+    // An op that failed has no ciphertext to serve
+    let ciphertext = result.outcome.as_ref().map_err(|_| StatusCode::NOT_FOUND)?;
+    Ok(Json(json!({
+        "handle": handle,
+        "fhe_type": ciphertext.fhe_type as u8,
+        "key_id": ciphertext.key_id,
+        "ciphertext": BASE64.encode(&ciphertext.bytes),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::CoprocessorState;
+    use crate::store::{Ciphertext, ResultStatus};
+    use crate::types::{EventMetadata, FheOperation, FheType, TrivialEncrypt};
+    use alloy::primitives::{Address, U256};
+
+    #[tokio::test]
+    async fn test_ciphertexts_require_the_kms_token() {
+        let state = CoprocessorState::new(0);
+        let handle = B256::repeat_byte(1);
+        let op = FheOperation::TrivialEncrypt(TrivialEncrypt {
+            metadata: EventMetadata {
+                block_number: 1,
+                tx_hash: None,
+                log_index: 0,
+                caller: Address::ZERO,
+            },
+            plaintext: U256::from(1),
+            to_type: FheType::Uint8,
+            result: handle,
+        });
+        let ciphertext = Ciphertext {
+            fhe_type: FheType::Uint8,
+            bytes: vec![1, 2, 3],
+            key_id: "1-aa".to_string(),
+        };
+        state
+            .store
+            .write()
+            .await
+            .insert(&op, Ok(ciphertext), None, ResultStatus::Final)
+            .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/ciphertexts/{}", listener.local_addr().unwrap(), handle);
+        let router = create_router(state, Some("kms-token".to_string()));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let http = reqwest::Client::new();
+        let status = |token: Option<&'static str>| {
+            let mut request = http.get(&url);
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            async move { request.send().await.unwrap() }
+        };
+        assert_eq!(status(None).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("other")).await.status(), StatusCode::UNAUTHORIZED);
+        let response = status(Some("kms-token")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["fhe_type"], FheType::Uint8 as u8);
+        assert_eq!(body["key_id"], "1-aa");
+        assert_eq!(body["ciphertext"], BASE64.encode([1, 2, 3]));
+    }
+}
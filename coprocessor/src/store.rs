@@ -0,0 +1,237 @@
+//! Result Store
+//! Keeps the outcome of every processed FHE operation keyed by its result handle.
+//! Results start out speculative and only become final once their block has enough
+//! confirmations; only final results are served to decryption.
+//! Each result is persisted to a file of its own, so results survive restarts along
+//! with the processed event index that refers to them.
+
+use crate::types::{FheOperation, FheType, Handle};
+use alloy::primitives::B256;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResultStatus {
+    /// Computed as soon as the log was seen, may still be reorged out
+    Speculative,
+    /// Block reached the configured confirmation depth
+    Final,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredResult {
+    pub block_number: u64,
+    pub block_hash: Option<B256>,
+    pub status: ResultStatus,
+    /// The op's ciphertext, or why it has none: ops reading it then fail too instead
+    /// of waiting for a result that never comes
+    pub outcome: Result<Ciphertext, String>,
+}
+
+/// Ciphertext computed for a handle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ciphertext {
+    pub fhe_type: FheType,
+    /// Bincode serialized
+    pub bytes: Vec<u8>,
+    /// KMS keyset whose server key computed it, the one that decrypts it
+    pub key_id: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ResultStore {
+    /// Directory holding one file per result, None keeps results in memory only
+    dir: Option<PathBuf>,
+    results: HashMap<Handle, StoredResult>,
+}
+
+impl ResultStore {
+    /// In-memory store, lost on restart
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the results persisted under `dir`, starting empty if there are none yet
+    pub fn load(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut results = HashMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            // Leftovers of a write interrupted before its rename
+            if path.extension().is_some() {
+                continue;
+            }
+            let handle = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.parse::<Handle>().ok())
+                .with_context(|| format!("Unexpected file {} in the result store", path.display()))?;
+            let result = bincode::deserialize(&fs::read(&path)?)
+                .with_context(|| format!("Corrupt result {}", path.display()))?;
+            results.insert(handle, result);
+        }
+        println!("[Store] loaded {} result(s) from {}", results.len(), dir.display());
+        Ok(Self {
+            dir: Some(dir.to_path_buf()),
+            results,
+        })
+    }
+
+    /// Record the outcome of an operation seen in `block_hash`
+    pub fn insert(
+        &mut self,
+        op: &FheOperation,
+        outcome: Result<Ciphertext, String>,
+        block_hash: Option<B256>,
+        status: ResultStatus,
+    ) -> Result<()> {
+        let (Some(handle), Some(metadata)) = (op.result_handle(), op.metadata()) else {
+            return Ok(());
+        };
+        let result = StoredResult {
+            block_number: metadata.block_number,
+            block_hash,
+            status,
+            outcome,
+        };
+        Self::write(self.dir.as_deref(), &handle, &result)?;
+        self.results.insert(handle, result);
+        Ok(())
+    }
+
+    /// Distinct (block number, block hash) pairs that still hold speculative results
+    /// at or below `up_to`
+    pub fn speculative_blocks(&self, up_to: u64) -> Vec<(u64, Option<B256>)> {
+        let blocks: BTreeSet<(u64, Option<B256>)> = self
+            .results
+            .values()
+            .filter(|r| r.status == ResultStatus::Speculative && r.block_number <= up_to)
+            .map(|r| (r.block_number, r.block_hash))
+            .collect();
+        blocks.into_iter().collect()
+    }
+
+    /// Mark every speculative result from `block_hash` as final
+    pub fn finalize_block(&mut self, block_hash: Option<B256>) -> Result<usize> {
+        let mut count = 0;
+        for (handle, result) in self.results.iter_mut() {
+            if result.status == ResultStatus::Speculative && result.block_hash == block_hash {
+                result.status = ResultStatus::Final;
+                Self::write(self.dir.as_deref(), handle, result)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Drop speculative results from a block that left the canonical chain
+    pub fn discard_block(&mut self, block_hash: Option<B256>) -> Result<usize> {
+        let discarded: Vec<Handle> = self
+            .results
+            .iter()
+            .filter(|(_, r)| r.status == ResultStatus::Speculative && r.block_hash == block_hash)
+            .map(|(handle, _)| *handle)
+            .collect();
+        for handle in &discarded {
+            if let Some(dir) = &self.dir {
+                fs::remove_file(dir.join(handle.to_string()))?;
+            }
+            self.results.remove(handle);
+        }
+        Ok(discarded.len())
+    }
+
+    /// Look up a result, speculative or not, as the input of a later operation
+    pub fn get(&self, handle: &Handle) -> Option<&StoredResult> {
+        self.results.get(handle)
+    }
+
+    /// Look up a result that is safe to decrypt
+    pub fn get_final(&self, handle: &Handle) -> Option<&StoredResult> {
+        self.results
+            .get(handle)
+            .filter(|r| r.status == ResultStatus::Final)
+    }
+
+    /// (speculative, final) result counts
+    pub fn counts(&self) -> (usize, usize) {
+        let finals = self
+            .results
+            .values()
+            .filter(|r| r.status == ResultStatus::Final)
+            .count();
+        (self.results.len() - finals, finals)
+    }
+
+    /// Write to a temp file and rename so a crash never leaves a truncated result
+    fn write(dir: Option<&Path>, handle: &Handle, result: &StoredResult) -> Result<()> {
+        let Some(dir) = dir else {
+            return Ok(());
+        };
+        let path = dir.join(handle.to_string());
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bincode::serialize(result)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::types::{EventMetadata, TrivialEncrypt};
+    use alloy::primitives::{Address, U256};
+
+    fn trivial(block_number: u64, result: u8) -> FheOperation {
+        FheOperation::TrivialEncrypt(TrivialEncrypt {
+            metadata: EventMetadata {
+                block_number,
+                tx_hash: None,
+                log_index: 0,
+                caller: Address::ZERO,
+            },
+            plaintext: U256::from(1),
+            to_type: FheType::Uint64,
+            result: B256::repeat_byte(result),
+        })
+    }
+
+    fn ciphertext(byte: u8) -> Result<Ciphertext, String> {
+        Ok(Ciphertext {
+            fhe_type: FheType::Uint64,
+            bytes: vec![byte],
+            key_id: "1-aa".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_finalize_and_discard() {
+        let dir = std::env::temp_dir().join(format!("coprocessor-results-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut store = ResultStore::load(&dir).unwrap();
+        let canonical = Some(B256::repeat_byte(0xaa));
+        let orphaned = Some(B256::repeat_byte(0xbb));
+        store.insert(&trivial(10, 1), ciphertext(1), canonical, ResultStatus::Speculative).unwrap();
+        store.insert(&trivial(11, 2), ciphertext(2), orphaned, ResultStatus::Speculative).unwrap();
+
+        assert!(store.get_final(&B256::repeat_byte(1)).is_none());
+        assert_eq!(store.speculative_blocks(10), vec![(10, canonical)]);
+
+        assert_eq!(store.finalize_block(canonical).unwrap(), 1);
+        assert!(store.get_final(&B256::repeat_byte(1)).is_some());
+
+        assert_eq!(store.discard_block(orphaned).unwrap(), 1);
+        assert_eq!(store.counts(), (0, 1));
+
+        // A restart finds the final result and not the discarded one
+        let store = ResultStore::load(&dir).unwrap();
+        assert_eq!(store.counts(), (0, 1));
+        let result = store.get_final(&B256::repeat_byte(1)).unwrap();
+        assert_eq!(result.outcome.as_ref().unwrap().bytes, vec![1]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}